@@ -2,6 +2,12 @@ use cargo_metadata::{
     camino::{Utf8Path, Utf8PathBuf},
     CargoOpt, Metadata, MetadataCommand,
 };
+use object::{
+    elf::FileHeader32,
+    read::{elf::FileHeader, Object, ObjectSection},
+    LittleEndian,
+};
+use psvita_sce_types::elf::{ET_SCE_EXEC, ET_SCE_RELEXEC};
 use std::{
     collections::HashSet,
     env, fs,
@@ -49,10 +55,13 @@ fn main() {
         let out_dir = elf.parent().unwrap();
         let stem = elf.file_stem().unwrap();
 
-        generate_velf(out_dir, stem);
+        generate_velf(&elf, out_dir, stem);
         generate_eboot(out_dir, stem);
         make_sfo(out_dir, stem, &opt.title);
         eprintln!("Produced vpk: {}", pack_vpk(out_dir, stem));
+        if let Some(debug) = copy_debug_file(&elf, out_dir, stem) {
+            eprintln!("Produced debug file: {}", debug);
+        }
     }
 }
 
@@ -75,7 +84,7 @@ fn get_metadata(opt: &Opt) -> Metadata {
 
 fn exists_or_create_dir(dir: &Utf8Path) {
     if !dir.exists() {
-        fs::create_dir_all(&dir).expect("could not create target directory");
+        fs::create_dir_all(dir).expect("could not create target directory");
     }
     assert!(dir.is_dir());
 }
@@ -99,15 +108,15 @@ fn build(opt: &Opt, metadata: &Metadata) -> Vec<Utf8PathBuf> {
 
     let out = Command::new(env::var("CARGO").unwrap_or(String::from("cargo")))
         .arg("build")
-        .args(&[
+        .args([
             "--target",
             metadata.target_directory.join(target_config::NAME).as_str(),
         ])
-        .args(&["-Z", "build-std=core"])
-        .args(&["--message-format", "json-render-diagnostics"])
+        .args(["-Z", "build-std=core"])
+        .args(["--message-format", "json-render-diagnostics"])
         .args(&opt.features)
-        .args(opt.all_features.then(|| "--all-features"))
-        .args(opt.no_default_features.then(|| "--no-default-features"))
+        .args(opt.all_features.then_some("--all-features"))
+        .args(opt.no_default_features.then_some("--no-default-features"))
         .args(&opt.manifest_path)
         .args(&opt.build_args)
         .env("RUSTFLAGS", rustflags)
@@ -134,13 +143,24 @@ fn build(opt: &Opt, metadata: &Metadata) -> Vec<Utf8PathBuf> {
         .collect()
 }
 
-fn generate_velf(out_dir: &Utf8Path, stem: &str) -> Utf8PathBuf {
-    let elf = out_dir.join(format!("{}.elf", stem));
+/// Produce `<stem>.velf`, running `vita-elf-create` only if the linker did not write a SCE
+/// ELF already.
+fn generate_velf(executable: &Utf8Path, out_dir: &Utf8Path, stem: &str) -> Utf8PathBuf {
     let output = out_dir.join(format!("{}.velf", stem));
+    if is_sce_elf(executable) {
+        if executable != output {
+            fs::copy(executable, &output).expect("could not copy the linked executable");
+        }
+        return output;
+    }
 
+    let elf = out_dir.join(format!("{}.elf", stem));
+    if executable != elf {
+        fs::copy(executable, &elf).expect("could not copy the linked executable");
+    }
     assert!(
         Command::new(vitasdk_bin().join("vita-elf-create"))
-            .args(&[&elf, &output])
+            .args([&elf, &output])
             .status()
             .expect("could not execute vita-elf-create")
             .success(),
@@ -149,6 +169,42 @@ fn generate_velf(out_dir: &Utf8Path, stem: &str) -> Utf8PathBuf {
     output
 }
 
+/// Whether the file is an ELF with the `ET_SCE_EXEC` or `ET_SCE_RELEXEC` type.
+fn is_sce_elf(path: &Utf8Path) -> bool {
+    let data = fs::read(path).expect("could not read the linked executable");
+    match FileHeader32::<LittleEndian>::parse(&*data) {
+        Ok(header) => matches!(header.e_type(LittleEndian), ET_SCE_EXEC | ET_SCE_RELEXEC),
+        Err(_) => false,
+    }
+}
+
+/// Copy the debug companion which the linker wrote for the executable to
+/// `<stem>.debug.elf`, next to the vpk.
+///
+/// The companion is named by the `.gnu_debuglink` section of the executable and sits next
+/// to the file the linker produced, which cargo may have copied out of `deps`.
+fn copy_debug_file(executable: &Utf8Path, out_dir: &Utf8Path, stem: &str) -> Option<Utf8PathBuf> {
+    let name = debug_link(executable)?;
+    let dir = executable.parent()?;
+    let candidates = [dir.join(&name), dir.join("deps").join(&name)];
+    let debug = candidates.iter().find(|path| path.is_file())?;
+
+    let output = out_dir.join(format!("{}.debug.elf", stem));
+    if *debug != output {
+        fs::copy(debug, &output).expect("could not copy the debug file");
+    }
+    Some(output)
+}
+
+/// File name in the `.gnu_debuglink` section of an ELF.
+fn debug_link(path: &Utf8Path) -> Option<String> {
+    let data = fs::read(path).expect("could not read the linked executable");
+    let file = object::File::parse(&*data).ok()?;
+    let link = file.section_by_name(".gnu_debuglink")?.data().ok()?;
+    let name = link.split(|&byte| byte == 0).next()?;
+    Some(String::from_utf8_lossy(name).into_owned())
+}
+
 fn generate_eboot(out_dir: &Utf8Path, stem: &str) -> Utf8PathBuf {
     let velf = out_dir.join(format!("{}.velf", stem));
     let output = out_dir.join(format!("{}.eboot.bin", stem));
@@ -156,7 +212,7 @@ fn generate_eboot(out_dir: &Utf8Path, stem: &str) -> Utf8PathBuf {
     assert!(
         Command::new(vitasdk_bin().join("vita-make-fself"))
             .arg("-s")
-            .args(&[&velf, &output])
+            .args([&velf, &output])
             .status()
             .expect("could not execute vita-make-fself")
             .success(),
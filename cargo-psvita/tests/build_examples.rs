@@ -29,7 +29,7 @@ fn build(package: &Path) {
     let linker_dir = workspace.join("target").join("debug");
     {
         let mut cmd = cargo_command();
-        cmd.args(&["build", "-ppsvita-linker"]);
+        cmd.args(["build", "-ppsvita-linker"]);
         eprintln!("{:?}", cmd);
         let status = cmd.status().unwrap();
         assert!(status.success());
@@ -42,8 +42,8 @@ fn build(package: &Path) {
     );
 
     let status = cargo_command()
-        .args(&["run", "-pcargo-psvita", "--"])
-        .args(&["--title=TEST0000", "--"])
+        .args(["run", "-pcargo-psvita", "--"])
+        .args(["--title=TEST0000", "--"])
         .arg("--manifest-path")
         .arg(workspace.join(package).join("Cargo.toml").to_str().unwrap())
         .arg("-v")
@@ -3,7 +3,9 @@
 
 #[no_mangle]
 pub unsafe extern "C" fn _start(_args: i32, _argp: *const u8) -> ! {
-    while psvita_dylib_example::foo() {}
+    loop {
+        psvita_dylib_example::foo();
+    }
 }
 
 #[panic_handler]
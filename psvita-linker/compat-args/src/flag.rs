@@ -44,7 +44,10 @@ impl<A> Flags<A> {
     {
         let producer = Box::new(producer);
         match self.inner.entry(flag) {
-            hash_map::Entry::Vacant(e) => Ok(drop(e.insert(producer))),
+            hash_map::Entry::Vacant(e) => {
+                e.insert(producer);
+                Ok(())
+            }
             hash_map::Entry::Occupied(_) => Err(DuplicateFlagError { flag }),
         }
     }
@@ -1,7 +1,9 @@
-use std::fmt;
+use std::{collections::VecDeque, fmt};
+use thiserror::Error;
 
 pub mod flag;
 pub mod long;
+pub mod response;
 pub mod short;
 
 /// Option handlers container.
@@ -37,26 +39,97 @@ impl<A> Args<A> {
         }
     }
 
-    /// Transform string iterator into arguments iterator
-    pub fn map_iter<'a, I>(&'a self, iter: I) -> impl Iterator<Item = A> + 'a
+    /// Transform string iterator into arguments iterator, expanding `@file` response files
+    pub fn map_iter<'a, I>(&'a self, iter: I) -> impl Iterator<Item = Result<A, ArgsError>> + 'a
+    where
+        I: Iterator<Item = String> + 'a,
+    {
+        self.map_iter_indexed(iter)
+            .map(|arg| arg.map(|(_, arg)| arg))
+    }
+
+    /// Same as [`Args::map_iter`], but with the index of the string each argument starts at.
+    pub fn map_iter_indexed<'a, I>(
+        &'a self,
+        iter: I,
+    ) -> impl Iterator<Item = Result<(usize, A), ArgsError>> + 'a
     where
         I: Iterator<Item = String> + 'a,
     {
         ArgsIter {
             args: self,
-            inner: iter.peekable(),
+            inner: response::Expand::new(iter),
+            pending: VecDeque::new(),
+            error: None,
         }
     }
 }
 
+impl<A> Default for Args<A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[doc(hidden)]
 pub const BUILD_SHORT_OPTION_ERROR_MSG: &str = "short option name should be 2 bytes long";
 
+/// An error which can be returned while transforming strings into arguments.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ArgsError {
+    #[error("unknown option `{option}`")]
+    UnknownOption { position: usize, option: String },
+    #[error("missing value for option `{option}`")]
+    MissingValue { position: usize, option: String },
+    #[error("unexpected argument `{argument}`")]
+    UnexpectedArgument { position: usize, argument: String },
+    #[error("cannot read response file `{argument}`: {reason}")]
+    ResponseFile {
+        position: usize,
+        argument: String,
+        reason: String,
+    },
+    #[error("response file `{argument}` includes itself")]
+    ResponseFileCycle { position: usize, argument: String },
+}
+
+impl ArgsError {
+    /// Index of the offending string.
+    pub fn position(&self) -> usize {
+        match *self {
+            ArgsError::UnknownOption { position, .. }
+            | ArgsError::MissingValue { position, .. }
+            | ArgsError::UnexpectedArgument { position, .. }
+            | ArgsError::ResponseFile { position, .. }
+            | ArgsError::ResponseFileCycle { position, .. } => position,
+        }
+    }
+
+    /// The offending string.
+    pub fn argument(&self) -> &str {
+        match self {
+            ArgsError::UnknownOption { option, .. } | ArgsError::MissingValue { option, .. } => {
+                option
+            }
+            ArgsError::UnexpectedArgument { argument, .. }
+            | ArgsError::ResponseFile { argument, .. }
+            | ArgsError::ResponseFileCycle { argument, .. } => argument,
+        }
+    }
+}
+
+/// Longest sequence of strings an argument is made of, an option and its value.
+const MAX_ARGUMENT_STRINGS: usize = 2;
+
 struct ArgsIter<'a, A, I>
 where
     I: Iterator<Item = String>,
 {
-    inner: std::iter::Peekable<I>,
+    inner: response::Expand<I>,
+    /// Strings read ahead, with their positions.
+    pending: VecDeque<(usize, String)>,
+    /// Error met while reading ahead, reported once the pending strings are parsed.
+    error: Option<ArgsError>,
     args: &'a Args<A>,
 }
 
@@ -64,36 +137,92 @@ impl<'a, A, I> Iterator for ArgsIter<'a, A, I>
 where
     I: Iterator<Item = String>,
 {
-    type Item = A;
+    type Item = Result<(usize, A), ArgsError>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        /// Return `Some` early
-        macro_rules! short_circuit {
-            ($e:expr) => {
-                if let Some(v) = $e {
-                    return v;
-                }
-            };
+        while self.pending.len() < MAX_ARGUMENT_STRINGS && self.error.is_none() {
+            match self.inner.next() {
+                Some(Ok(string)) => self.pending.push_back(string),
+                Some(Err(e)) => self.error = Some(e),
+                None => break,
+            }
         }
-
-        // return if end of iterator
-        let _ = self.inner.peek()?;
+        let (position, current) = match self.pending.front() {
+            Some((position, current)) => (*position, current.clone()),
+            None => return self.error.take().map(Err),
+        };
 
         // try to parse arguments in this concrete order to satisfy parsing assumptions
-        short_circuit!(self.args.flags.parse_argument(&mut self.inner).map(Some));
-        short_circuit!(self.args.shorts.parse_argument(&mut self.inner).map(Some));
-        short_circuit!(self.args.longs.parse_argument(&mut self.inner).map(Some));
+        let args = self.args;
+        let parsed = self
+            .parse_option(position, &current, |w| args.flags.parse_argument(w))
+            .or_else(|| self.parse_option(position, &current, |w| args.shorts.parse_argument(w)))
+            .or_else(|| self.parse_option(position, &current, |w| args.longs.parse_argument(w)));
+        if parsed.is_some() {
+            return parsed;
+        }
 
-        let other = self.inner.next().unwrap();
+        let (_, other) = self.pending.pop_front().unwrap();
         if other.starts_with('-') {
-            panic!("unhandled option: {}", &other);
+            return Some(Err(ArgsError::UnknownOption {
+                position,
+                option: other,
+            }));
+        }
+        match &args.plain_handler {
+            Some(handler) => Some(Ok((position, handler(other)))),
+            None => Some(Err(ArgsError::UnexpectedArgument {
+                position,
+                argument: other,
+            })),
+        }
+    }
+}
+
+impl<'a, A, I> ArgsIter<'a, A, I>
+where
+    I: Iterator<Item = String>,
+{
+    /// Run an option parser over the pending strings, `None` if it did not match.
+    fn parse_option<F>(
+        &mut self,
+        position: usize,
+        current: &str,
+        parse: F,
+    ) -> Option<Result<(usize, A), ArgsError>>
+    where
+        F: FnOnce(&mut Window<'_>) -> Option<A>,
+    {
+        let mut window = self
+            .pending
+            .iter()
+            .map(string as fn(&(usize, String)) -> String)
+            .peekable();
+        let parsed = parse(&mut window);
+        let consumed = self.pending.len() - window.count();
+        if consumed == 0 {
+            return None;
         }
-        Some((self
-            .args
-            .plain_handler
-            .as_ref()
-            .expect("did not found plain arguments handler"))(
-            other
-        ))
+        self.pending.drain(..consumed);
+        Some(match parsed {
+            Some(arg) => Ok((position, arg)),
+            // an option without its value consumes the rest of the strings
+            None => Err(ArgsError::MissingValue {
+                position,
+                option: current.to_owned(),
+            }),
+        })
     }
 }
+
+/// Strings read ahead, as option parsers see them.
+type Window<'p> = std::iter::Peekable<
+    std::iter::Map<
+        std::collections::vec_deque::Iter<'p, (usize, String)>,
+        fn(&(usize, String)) -> String,
+    >,
+>;
+
+fn string(pending: &(usize, String)) -> String {
+    pending.1.clone()
+}
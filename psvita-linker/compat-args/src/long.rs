@@ -46,7 +46,10 @@ impl<A> Longs<A> {
     {
         let parser = Box::new(move |s: String| parser(s.into()));
         match self.inner.entry(name) {
-            hash_map::Entry::Vacant(e) => Ok(drop(e.insert(parser))),
+            hash_map::Entry::Vacant(e) => {
+                e.insert(parser);
+                Ok(())
+            }
             hash_map::Entry::Occupied(_) => Err(DuplicateLongError { name }),
         }
     }
@@ -79,8 +82,7 @@ impl<A> Longs<A> {
             v
         } else {
             let _ = args.next()?;
-            let v = args.next()?;
-            v
+            args.next()?
         }))
     }
 }
@@ -0,0 +1,143 @@
+//! Response files, `@file` arguments which stand for the arguments written in the file.
+//!
+//! Arguments in a file are separated by whitespace. Single and double quotes group
+//! characters including whitespace into one argument, and a backslash takes the next
+//! character literally, even inside quotes. This follows the GNU `libiberty` rules, which
+//! is what `rustc` expects when it falls back to a response file.
+
+use super::ArgsError;
+use std::{fs, iter::Fuse, path::PathBuf, vec};
+
+/// Split the contents of a response file into arguments.
+pub fn split(text: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut chars = text.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            return args;
+        }
+
+        let mut arg = String::new();
+        let (mut squote, mut dquote, mut bsquote) = (false, false, false);
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() && !squote && !dquote && !bsquote {
+                break;
+            }
+            chars.next();
+            if bsquote {
+                bsquote = false;
+                arg.push(c);
+            } else if c == '\\' {
+                bsquote = true;
+            } else if squote {
+                if c == '\'' {
+                    squote = false;
+                } else {
+                    arg.push(c);
+                }
+            } else if dquote {
+                if c == '"' {
+                    dquote = false;
+                } else {
+                    arg.push(c);
+                }
+            } else if c == '\'' {
+                squote = true;
+            } else if c == '"' {
+                dquote = true;
+            } else {
+                arg.push(c);
+            }
+        }
+        args.push(arg);
+    }
+}
+
+/// Response file being read.
+struct Included {
+    /// Canonical path, to detect cycles.
+    path: PathBuf,
+    args: vec::IntoIter<String>,
+}
+
+/// String iterator with response files expanded.
+///
+/// Yields every argument with the index of the string it came from, arguments read from
+/// response files get the index of the outermost `@file`.
+pub(crate) struct Expand<I> {
+    inner: Fuse<I>,
+    /// Index of the next string of `inner`.
+    index: usize,
+    /// Index of the outermost response file being read.
+    position: usize,
+    stack: Vec<Included>,
+}
+
+impl<I> Expand<I>
+where
+    I: Iterator<Item = String>,
+{
+    pub(crate) fn new(inner: I) -> Self {
+        Self {
+            inner: inner.fuse(),
+            index: 0,
+            position: 0,
+            stack: Vec::new(),
+        }
+    }
+
+    fn include(&mut self, argument: String) -> Result<(), ArgsError> {
+        let position = self.position;
+        let error = |reason: String| ArgsError::ResponseFile {
+            position,
+            argument: argument.clone(),
+            reason,
+        };
+        let path = fs::canonicalize(&argument[1..]).map_err(|e| error(e.to_string()))?;
+        if self.stack.iter().any(|included| included.path == path) {
+            return Err(ArgsError::ResponseFileCycle { position, argument });
+        }
+        let text = fs::read_to_string(&path).map_err(|e| error(e.to_string()))?;
+        self.stack.push(Included {
+            path,
+            args: split(&text).into_iter(),
+        });
+        Ok(())
+    }
+}
+
+impl<I> Iterator for Expand<I>
+where
+    I: Iterator<Item = String>,
+{
+    type Item = Result<(usize, String), ArgsError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let arg = match self.stack.last_mut() {
+                Some(included) => match included.args.next() {
+                    Some(arg) => arg,
+                    None => {
+                        self.stack.pop();
+                        continue;
+                    }
+                },
+                None => {
+                    let arg = self.inner.next()?;
+                    self.position = self.index;
+                    self.index += 1;
+                    arg
+                }
+            };
+
+            if arg.len() > 1 && arg.starts_with('@') {
+                if let Err(e) = self.include(arg) {
+                    return Some(Err(e));
+                }
+            } else {
+                return Some(Ok((self.position, arg)));
+            }
+        }
+    }
+}
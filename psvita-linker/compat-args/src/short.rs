@@ -70,7 +70,10 @@ impl<A> Shorts<A> {
     {
         let parser = Box::new(move |s: String| parser(s.into()));
         match self.inner.entry(name) {
-            hash_map::Entry::Vacant(e) => Ok(drop(e.insert(parser))),
+            hash_map::Entry::Vacant(e) => {
+                e.insert(parser);
+                Ok(())
+            }
             hash_map::Entry::Occupied(_) => Err(DuplicateShortError { name }),
         }
     }
@@ -95,8 +98,7 @@ impl<A> Shorts<A> {
         let parser = self.inner.get(name)?;
         Some(parser(if arg.len() <= 2 {
             let _ = args.next()?;
-            let v = args.next()?;
-            v
+            args.next()?
         } else {
             let mut v = args.next()?;
             v.replace_range(..2, "");
@@ -1,6 +1,8 @@
 use std::path::PathBuf;
 use thiserror::Error;
 
+pub use compat_args::ArgsError;
+
 #[remain::sorted]
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Argument {
@@ -8,13 +10,21 @@ pub enum Argument {
     BDynamic,
     BStatic,
     EhFrameHdr,
+    /// `--end-group`, closing a group of archives.
+    EndGroup,
     GcSections(bool),
     InputFile(PathBuf),
     Library(Library),
     LibraryPath(PathBuf),
+    /// `-Map`, with `-` for the standard output.
+    Map(PathBuf),
     Output(PathBuf),
     PicExecutable,
+    PrintMap,
     Shared,
+    /// `--start-group`, opening a group of archives which are searched repeatedly until no
+    /// new undefined references are created.
+    StartGroup,
     VersionScript(PathBuf),
     WholeArchive(bool),
     Z(ZKeyword),
@@ -37,14 +47,51 @@ impl From<String> for Library {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Keyword of `-z`, with the spelling of GNU ld.
+#[remain::sorted]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ZKeyword {
-    Noexecstack,
+    /// `combreloc` or `nocombreloc`
+    CombReloc(bool),
+    /// `common-page-size=`
+    CommonPageSize(u64),
+    /// `copyreloc` or `nocopyreloc`
+    CopyReloc(bool),
+    /// `defs` or `undefs`
+    Defs(bool),
+    /// `dynamic-undefined-weak` or `nodynamic-undefined-weak`
+    DynamicUndefinedWeak(bool),
+    /// `execstack` or `noexecstack`
+    ExecStack(bool),
+    Global,
+    InitFirst,
+    Interpose,
+    LoadFltr,
+    /// `max-page-size=`
+    MaxPageSize(u64),
+    MulDefs,
+    NoDefaultLib,
+    NoDelete,
+    NoDlopen,
+    NoDump,
+    /// `now` or `lazy`
+    Now(bool),
+    Origin,
+    /// `relro` or `norelro`
+    Relro(bool),
+    /// `separate-code` or `noseparate-code`
+    SeparateCode(bool),
+    /// `stack-size=`
+    StackSize(u64),
+    /// `text`, or `notext` and `textoff`
+    Text(bool),
+    /// Keyword which is not known or has a malformed value, as it was given.
+    Unknown(String),
 }
 
 impl From<String> for ZKeyword {
     fn from(s: String) -> Self {
-        s.parse().expect("parsing `-z` option")
+        s.parse().unwrap_or(ZKeyword::Unknown(s))
     }
 }
 
@@ -52,19 +99,106 @@ impl From<String> for ZKeyword {
 pub enum ParseZKeywordError {
     #[error("unknown -z keyword")]
     Unknown,
+    #[error("invalid value of -z keyword")]
+    InvalidValue,
 }
 
 impl std::str::FromStr for ZKeyword {
     type Err = ParseZKeywordError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "noexecstack" => Ok(ZKeyword::Noexecstack),
-            _ => Err(ParseZKeywordError::Unknown),
+        use ZKeyword::*;
+
+        if let Some((keyword, value)) = s.split_once('=') {
+            let value = parse_number(value).ok_or(ParseZKeywordError::InvalidValue)?;
+            return match keyword {
+                "common-page-size" => Ok(CommonPageSize(value)),
+                "max-page-size" => Ok(MaxPageSize(value)),
+                "stack-size" => Ok(StackSize(value)),
+                _ => Err(ParseZKeywordError::Unknown),
+            };
+        }
+
+        Ok(match s {
+            "combreloc" => CombReloc(true),
+            "nocombreloc" => CombReloc(false),
+            "copyreloc" => CopyReloc(true),
+            "nocopyreloc" => CopyReloc(false),
+            "defs" => Defs(true),
+            "undefs" => Defs(false),
+            "dynamic-undefined-weak" => DynamicUndefinedWeak(true),
+            "nodynamic-undefined-weak" => DynamicUndefinedWeak(false),
+            "execstack" => ExecStack(true),
+            "noexecstack" => ExecStack(false),
+            "global" => Global,
+            "initfirst" => InitFirst,
+            "interpose" => Interpose,
+            "loadfltr" => LoadFltr,
+            "muldefs" => MulDefs,
+            "nodefaultlib" => NoDefaultLib,
+            "nodelete" => NoDelete,
+            "nodlopen" => NoDlopen,
+            "nodump" => NoDump,
+            "now" => Now(true),
+            "lazy" => Now(false),
+            "origin" => Origin,
+            "relro" => Relro(true),
+            "norelro" => Relro(false),
+            "separate-code" => SeparateCode(true),
+            "noseparate-code" => SeparateCode(false),
+            "text" => Text(true),
+            "notext" | "textoff" => Text(false),
+            _ => return Err(ParseZKeywordError::Unknown),
+        })
+    }
+}
+
+impl std::fmt::Display for ZKeyword {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use ZKeyword::*;
+
+        let no = |enabled: &bool| if *enabled { "" } else { "no" };
+        match self {
+            CombReloc(enabled) => write!(f, "{}combreloc", no(enabled)),
+            CommonPageSize(value) => write!(f, "common-page-size={:#x}", value),
+            CopyReloc(enabled) => write!(f, "{}copyreloc", no(enabled)),
+            Defs(true) => f.write_str("defs"),
+            Defs(false) => f.write_str("undefs"),
+            DynamicUndefinedWeak(enabled) => write!(f, "{}dynamic-undefined-weak", no(enabled)),
+            ExecStack(enabled) => write!(f, "{}execstack", no(enabled)),
+            Global => f.write_str("global"),
+            InitFirst => f.write_str("initfirst"),
+            Interpose => f.write_str("interpose"),
+            LoadFltr => f.write_str("loadfltr"),
+            MaxPageSize(value) => write!(f, "max-page-size={:#x}", value),
+            MulDefs => f.write_str("muldefs"),
+            NoDefaultLib => f.write_str("nodefaultlib"),
+            NoDelete => f.write_str("nodelete"),
+            NoDlopen => f.write_str("nodlopen"),
+            NoDump => f.write_str("nodump"),
+            Now(true) => f.write_str("now"),
+            Now(false) => f.write_str("lazy"),
+            Origin => f.write_str("origin"),
+            Relro(enabled) => write!(f, "{}relro", no(enabled)),
+            SeparateCode(enabled) => write!(f, "{}separate-code", no(enabled)),
+            StackSize(value) => write!(f, "stack-size={:#x}", value),
+            Text(enabled) => write!(f, "{}text", no(enabled)),
+            Unknown(keyword) => f.write_str(keyword),
         }
     }
 }
 
+/// Number in the decimal, `0x` hexadecimal or `0` octal notation.
+fn parse_number(s: &str) -> Option<u64> {
+    if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        u64::from_str_radix(hex, 16).ok()
+    } else if s.len() > 1 && s.starts_with('0') {
+        u64::from_str_radix(&s[1..], 8).ok()
+    } else {
+        s.parse().ok()
+    }
+}
+
 pub fn args() -> compat_args::Args<self::Argument> {
     use Argument::*;
 
@@ -94,6 +228,14 @@ pub fn args() -> compat_args::Args<self::Argument> {
 
     flags.insert("--eh-frame-hdr", || EhFrameHdr);
 
+    let handler = || StartGroup;
+    flags.insert("--start-group", handler);
+    flags.insert("-(", handler);
+
+    let handler = || EndGroup;
+    flags.insert("--end-group", handler);
+    flags.insert("-)", handler);
+
     flags.insert("--gc-sections", || GcSections(true));
     flags.insert("--no-gc-sections", || GcSections(false));
 
@@ -101,6 +243,10 @@ pub fn args() -> compat_args::Args<self::Argument> {
     flags.insert("-pie", handler);
     flags.insert("--pic-executable", handler);
 
+    let handler = || PrintMap;
+    flags.insert("-M", handler);
+    flags.insert("--print-map", handler);
+
     let handler = || Shared;
     flags.insert("-shared", handler);
     flags.insert("-Bshareable", handler);
@@ -120,6 +266,9 @@ pub fn args() -> compat_args::Args<self::Argument> {
     shorts.insert(*b"-o", handler);
     longs.insert("--output", handler);
 
+    longs.insert("-Map", Map);
+    longs.insert("--Map", Map);
+
     longs.insert("--version-script", VersionScript);
 
     shorts.insert(*b"-z", Z);
@@ -134,7 +283,128 @@ mod tests {
         let input_args = ["--version-script=/tmp/rustchCaNJl/list", "/home/zeta0/rust-psvita/examples/target/armv7a-sony-psvita/debug/deps/psvita_dylib_example.420ogeym4143ooxn.rcgu.o", "/home/zeta0/rust-psvita/examples/target/armv7a-sony-psvita/debug/deps/psvita_dylib_example.2jdmp6cbjz7q76p1.rcgu.o", "--as-needed", "-L", "/home/zeta0/rust-psvita/examples/target/armv7a-sony-psvita/debug/deps", "-L", "/home/zeta0/rust-psvita/examples/target/debug/deps", "-L", "/home/zeta0/.vitasdk/arm-vita-eabi/lib", "-L", "/home/zeta0/.rustup/toolchains/nightly-x86_64-unknown-linux-gnu/lib/rustlib/armv7a-sony-psvita/lib", "-Bstatic", "--whole-archive", "/home/zeta0/rust-psvita/examples/target/armv7a-sony-psvita/debug/deps/librustc_std_workspace_core-d03d8b57bcedbd94.rlib", "--no-whole-archive", "--whole-archive", "/home/zeta0/rust-psvita/examples/target/armv7a-sony-psvita/debug/deps/libcore-82cbd9ce51306110.rlib", "--no-whole-archive", "/home/zeta0/rust-psvita/examples/target/armv7a-sony-psvita/debug/deps/libcompiler_builtins-6d33f699b0f7befc.rlib", "-Bdynamic", "--eh-frame-hdr", "-znoexecstack", "-L", "/home/zeta0/.rustup/toolchains/nightly-x86_64-unknown-linux-gnu/lib/rustlib/armv7a-sony-psvita/lib", "-o", "/home/zeta0/rust-psvita/examples/target/armv7a-sony-psvita/debug/deps/libpsvita_dylib_example.vso", "-shared"];
         let args: Vec<_> = super::args()
             .map_iter(input_args.iter().map(|&s| s.to_owned()))
-            .collect();
+            .collect::<Result<_, _>>()
+            .unwrap();
         eprintln!("{:#?}", args);
     }
+
+    #[test]
+    fn parse_z_keywords() {
+        use super::{Argument::Z, ZKeyword::*};
+
+        let input_args = [
+            "-znoexecstack",
+            "-z",
+            "relro",
+            "-zmax-page-size=0x4000",
+            "-z",
+            "common-page-size=4096",
+            "-ztextoff",
+            "-zmax-page-size=lots",
+            "-zfrobnicate",
+        ];
+        let args: Vec<_> = super::args()
+            .map_iter(input_args.iter().map(|&s| s.to_owned()))
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            args,
+            [
+                Z(ExecStack(false)),
+                Z(Relro(true)),
+                Z(MaxPageSize(0x4000)),
+                Z(CommonPageSize(4096)),
+                Z(Text(false)),
+                Z(Unknown(String::from("max-page-size=lots"))),
+                Z(Unknown(String::from("frobnicate"))),
+            ]
+        );
+        assert_eq!(MaxPageSize(0x4000).to_string(), "max-page-size=0x4000");
+        assert_eq!(Now(false).to_string(), "lazy");
+    }
+
+    #[test]
+    fn report_argument_errors() {
+        use super::{ArgsError, Argument::*};
+
+        let input_args = ["main.o", "--frobnicate", "-o", "out.elf", "-L"];
+        let args: Vec<_> = super::args()
+            .map_iter_indexed(input_args.iter().map(|&s| s.to_owned()))
+            .collect();
+        assert_eq!(
+            args,
+            [
+                Ok((0, InputFile("main.o".into()))),
+                Err(ArgsError::UnknownOption {
+                    position: 1,
+                    option: "--frobnicate".to_owned()
+                }),
+                Ok((2, Output("out.elf".into()))),
+                Err(ArgsError::MissingValue {
+                    position: 4,
+                    option: "-L".to_owned()
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn expand_response_files() {
+        use super::{ArgsError, Argument::*};
+        use std::{env, fs};
+
+        let dir = env::temp_dir().join(format!("ld-compat-args-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let outer = dir.join("outer");
+        let inner = dir.join("inner");
+        let cycle = dir.join("cycle");
+        fs::write(
+            &outer,
+            format!(
+                "-L '/with space/lib'\n--gc-sections @{} \"quoted \\\" name.o\"",
+                inner.display()
+            ),
+        )
+        .unwrap();
+        fs::write(&inner, "-o out\\ file.elf ''").unwrap();
+        fs::write(&cycle, format!("main.o @{}", cycle.display())).unwrap();
+
+        let input_args = [
+            String::from("first.o"),
+            format!("@{}", outer.display()),
+            String::from("-shared"),
+            format!("@{}", cycle.display()),
+            format!("@{}", dir.join("missing").display()),
+        ];
+        let args: Vec<_> = super::args()
+            .map_iter_indexed(input_args.iter().cloned())
+            .collect();
+        assert_eq!(
+            args[..7],
+            [
+                Ok((0, InputFile("first.o".into()))),
+                Ok((1, LibraryPath("/with space/lib".into()))),
+                Ok((1, GcSections(true))),
+                Ok((1, Output("out file.elf".into()))),
+                Ok((1, InputFile("".into()))),
+                Ok((1, InputFile("quoted \" name.o".into()))),
+                Ok((2, Shared)),
+            ]
+        );
+        assert_eq!(
+            args[7..9],
+            [
+                Ok((3, InputFile("main.o".into()))),
+                Err(ArgsError::ResponseFileCycle {
+                    position: 3,
+                    argument: input_args[3].clone()
+                }),
+            ]
+        );
+        assert!(matches!(
+            args[9],
+            Err(ArgsError::ResponseFile { position: 4, .. })
+        ));
+        assert_eq!(args.len(), 10);
+    }
 }
@@ -112,9 +112,7 @@ impl GlobSet {
                 None => (),
 
                 // exact duplicate (do nothing)
-                Some(pat)
-                    if pat.0.prefix.as_slice() == pattern.as_ref() && pat.0.has_suffix == true =>
-                {
+                Some(pat) if pat.0.prefix.as_slice() == pattern.as_ref() && pat.0.has_suffix => {
                     return
                 }
 
@@ -151,13 +149,13 @@ impl GlobSet {
     {
         let mut middle = match range.start_bound() {
             Bound::Included(b) => self.patterns.split_off(b),
-            Bound::Excluded(b) => self.patterns.split_off(next_lexicographic(&b).as_slice()),
+            Bound::Excluded(b) => self.patterns.split_off(next_lexicographic(b).as_slice()),
             Bound::Unbounded => mem::take(&mut self.patterns),
         };
 
         let mut end = match range.end_bound() {
             Bound::Excluded(b) => middle.split_off(b),
-            Bound::Included(b) => match prev_lexicographic(&b) {
+            Bound::Included(b) => match prev_lexicographic(b) {
                 Some(prefix) => middle.split_off(prefix.as_ref()),
                 None => mem::take(&mut middle),
             },
@@ -64,7 +64,7 @@ impl TrivialVersionScript {
                 };
                 let copy_path =
                     env::temp_dir().join(format!("{:16x}-psvita-linker.version-script", hash));
-                let dump = fs::write(&copy_path, &text).map(move |()| copy_path);
+                let dump = fs::write(&copy_path, text).map(move |()| copy_path);
 
                 let dump_msg = match &dump {
                     Ok(path) => {
@@ -102,6 +102,12 @@ impl TrivialVersionScript {
     }
 }
 
+impl Default for TrivialVersionScript {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl std::str::FromStr for TrivialVersionScript {
     type Err = ParseTrivialVersionScriptError;
 
@@ -175,7 +181,7 @@ fn parse_token(
         got: None,
         kind: UnexpectedEnd,
     })?;
-    if expected.iter().any(|&t| t == got) {
+    if expected.contains(&got) {
         Ok(got)
     } else {
         Err(ParseTrivialVersionScriptError {
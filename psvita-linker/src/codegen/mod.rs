@@ -1,7 +1,7 @@
-mod verification;
+pub mod verification;
 
-type VitaEndian = object::LittleEndian;
-const VITA_ENDIAN: VitaEndian = VitaEndian {};
+pub type VitaEndian = object::LittleEndian;
+pub const VITA_ENDIAN: VitaEndian = VitaEndian {};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum ItemType {
@@ -9,4 +9,3 @@ pub enum ItemType {
     Variable,
     TLS,
 }
-
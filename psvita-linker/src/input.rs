@@ -1,17 +1,19 @@
-use ld_compat_args::{Argument, ZKeyword};
-use ld_version_script::TrivialVersionScript;
-use once_cell::unsync::OnceCell;
-use std::{env, fs, path::PathBuf};
+use ld_compat_args::{ArgsError, Argument, ZKeyword};
+use ld_version_script::{ParseTrivialVersionScriptError, TrivialVersionScript};
+use std::{env, fs, io, path::PathBuf};
+use thiserror::Error;
 
 #[derive(Debug)]
 pub struct Input {
-    pub input_files: Vec<InputFile>,
+    /// Files, libraries and groups in command line order.
+    pub inputs: Vec<InputNode>,
     pub library_paths: Vec<PathBuf>,
-    pub libraries: Vec<InputLibrary>,
     pub output_file: PathBuf,
     pub output_options: OutputOptions,
     pub eh_frame_header: bool,
     pub z_keywords: Vec<ZKeyword>,
+    /// Where to write the link map, `-` for the standard output.
+    pub map_file: Option<PathBuf>,
 }
 
 #[derive(Debug)]
@@ -25,10 +27,21 @@ pub enum OutputOptions {
 }
 
 #[derive(Debug)]
+pub enum InputNode {
+    File(InputFile),
+    Library(InputLibrary),
+    /// Nodes between `--start-group` and `--end-group`, whose archives are rescanned until
+    /// they stop loading members. Groups do not nest, so these are never groups themselves.
+    Group(Vec<InputNode>),
+}
+
+#[derive(Debug, Clone)]
 pub struct InputFile {
     pub path: PathBuf,
     pub gc_sections: bool,
     pub whole_archive: bool,
+    /// Import the stubs of the file only when they are referenced.
+    pub as_needed: bool,
 }
 
 #[derive(Debug)]
@@ -37,80 +50,226 @@ pub struct InputLibrary {
     pub only_static: bool,
     pub gc_sections: bool,
     pub whole_archive: bool,
+    /// Import from the library only the stubs which are referenced, leaving the library out
+    /// of the imports when there are none.
+    pub as_needed: bool,
+}
+
+/// Invalid command line, `position` is the index of the offending argument counting the
+/// program name as 0.
+///
+/// Arguments read from a response file are reported as the `@file` argument.
+#[derive(Error, Debug)]
+pub enum InputError {
+    #[error("{source} (argument {})", source.position() + 1)]
+    Argument { source: ArgsError },
+
+    #[error("output file specified again by `{argument}` (argument {position})")]
+    DuplicateOutput { position: usize, argument: String },
+
+    #[error("version script specified again by `{argument}` (argument {position})")]
+    DuplicateVersionScript { position: usize, argument: String },
+
+    #[error("cannot read version script `{}` (argument {position}): {source}", path.display())]
+    ReadVersionScript {
+        position: usize,
+        path: PathBuf,
+        source: io::Error,
+    },
+
+    #[error("cannot parse version script `{}` (argument {position}): {source}", path.display())]
+    ParseVersionScript {
+        position: usize,
+        path: PathBuf,
+        source: ParseTrivialVersionScriptError,
+    },
+
+    #[error("`{argument}` (argument {position}) inside of another group")]
+    NestedGroup { position: usize, argument: String },
+
+    #[error("`{argument}` (argument {position}) without a group to end")]
+    UnmatchedEndGroup { position: usize, argument: String },
+
+    #[error("cannot infer type of output file, `{argument}` (argument {position}) {reason}")]
+    OutputType {
+        position: usize,
+        argument: String,
+        reason: &'static str,
+    },
+}
+
+impl InputError {
+    /// Index of the offending argument, the program name being 0.
+    pub fn position(&self) -> usize {
+        match *self {
+            InputError::Argument { ref source } => source.position() + 1,
+            InputError::DuplicateOutput { position, .. }
+            | InputError::DuplicateVersionScript { position, .. }
+            | InputError::ReadVersionScript { position, .. }
+            | InputError::ParseVersionScript { position, .. }
+            | InputError::NestedGroup { position, .. }
+            | InputError::UnmatchedEndGroup { position, .. }
+            | InputError::OutputType { position, .. } => position,
+        }
+    }
 }
 
 impl Input {
-    pub fn from_args() -> Self {
-        let args = ld_compat_args::args();
-        let args = args.map_iter(env::args().skip(1));
+    /// Parse the arguments of the process.
+    pub fn from_args() -> Result<Self, InputError> {
+        Self::parse(env::args().skip(1))
+    }
 
-        let mut input_files = Vec::new();
+    /// Parse `ld` style arguments, which exclude the program name.
+    pub fn parse<I>(args: I) -> Result<Self, InputError>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let args: Vec<_> = args.into_iter().collect();
+        let parser = ld_compat_args::args();
+        // strings as they were given, to show in errors
+        let argument = |index: usize| args[index].clone();
+
+        let mut inputs = Vec::new();
+        // nodes of the group being read
+        let mut group: Option<Vec<InputNode>> = None;
         let mut library_paths = Vec::new();
 
-        let mut libraries = Vec::new();
         let mut only_static = false;
+        let mut as_needed = false;
         let mut gc_sections = false;
         let mut whole_archive = false;
 
-        let output_file = OnceCell::new();
-        let version_script = OnceCell::new();
-        let mut pie = false;
-        let mut shared = false;
+        let mut output_file = None;
+        let mut version_script = None;
+        // positions of the arguments which decide the type of the output
+        let mut pie = None;
+        let mut shared = None;
         let mut eh_frame_header = false;
         let mut z_keywords = Vec::new();
+        let mut map_file = None;
 
-        for arg in args {
+        for arg in parser.map_iter_indexed(args.iter().cloned()) {
+            let (index, arg) = arg.map_err(|source| InputError::Argument { source })?;
+            let position = index + 1;
+            let nodes = group.as_mut().unwrap_or(&mut inputs);
             match arg {
-                Argument::AsNeeded(_) => (),
+                Argument::AsNeeded(a) => as_needed = a,
                 Argument::BDynamic => only_static = false,
                 Argument::BStatic => only_static = true,
                 Argument::EhFrameHdr => eh_frame_header = true,
                 Argument::GcSections(p) => gc_sections = p,
-                Argument::InputFile(path) => input_files.push(InputFile {
+                Argument::EndGroup => match group.take() {
+                    Some(nodes) => inputs.push(InputNode::Group(nodes)),
+                    None => {
+                        return Err(InputError::UnmatchedEndGroup {
+                            position,
+                            argument: argument(index),
+                        })
+                    }
+                },
+                Argument::InputFile(path) => nodes.push(InputNode::File(InputFile {
                     path,
                     gc_sections,
                     whole_archive,
-                }),
-                Argument::Library(lib) => libraries.push(InputLibrary {
+                    as_needed,
+                })),
+                Argument::Library(lib) => nodes.push(InputNode::Library(InputLibrary {
                     lib,
                     only_static,
                     gc_sections,
                     whole_archive,
-                }),
+                    as_needed,
+                })),
                 Argument::LibraryPath(p) => library_paths.push(p),
-                Argument::Output(o) => output_file.set(o).expect("output file specified two times"),
-                Argument::PicExecutable => pie = true,
-                Argument::Shared => shared = true,
+                Argument::Map(path) => map_file = Some(path),
+                Argument::Output(o) => {
+                    if output_file.replace(o).is_some() {
+                        return Err(InputError::DuplicateOutput {
+                            position,
+                            argument: argument(index),
+                        });
+                    }
+                }
+                Argument::PicExecutable => pie = Some(position),
+                Argument::PrintMap => map_file = Some(PathBuf::from("-")),
+                Argument::Shared => shared = Some(position),
+                Argument::StartGroup => {
+                    if group.replace(Vec::new()).is_some() {
+                        return Err(InputError::NestedGroup {
+                            position,
+                            argument: argument(index),
+                        });
+                    }
+                }
                 Argument::VersionScript(path) => {
-                    let text = fs::read_to_string(&path).expect("cannot read version script");
-                    version_script
-                        .set(TrivialVersionScript::pretty_parse(&text))
-                        .expect("specified multiple version scripts");
+                    if version_script.is_some() {
+                        return Err(InputError::DuplicateVersionScript {
+                            position,
+                            argument: argument(index),
+                        });
+                    }
+                    let text = match fs::read_to_string(&path) {
+                        Ok(text) => text,
+                        Err(source) => {
+                            return Err(InputError::ReadVersionScript {
+                                position,
+                                path,
+                                source,
+                            })
+                        }
+                    };
+                    let script = text
+                        .parse()
+                        .map_err(|source| InputError::ParseVersionScript {
+                            position,
+                            path,
+                            source,
+                        })?;
+                    version_script = Some((position, script));
                 }
                 Argument::WholeArchive(w) => whole_archive = w,
                 Argument::Z(z) => z_keywords.push(z),
             }
         }
 
-        let output_options = match (pie, shared, version_script.into_inner()) {
-            (pic, false, None) => OutputOptions::Executable { pic },
-            (false, true, vs) => OutputOptions::Shared { version_script: vs },
-            _ => panic!("cannot infer type of output file"),
+        // like `ld`, a group left open ends with the command line
+        if let Some(nodes) = group {
+            inputs.push(InputNode::Group(nodes));
+        }
+
+        let output_type = |position: usize, reason| InputError::OutputType {
+            position,
+            argument: argument(position - 1),
+            reason,
+        };
+        let output_options = match (pie, shared, version_script) {
+            (pie, None, None) => OutputOptions::Executable { pic: pie.is_some() },
+            (None, Some(_), vs) => OutputOptions::Shared {
+                version_script: vs.map(|(_, vs)| vs),
+            },
+            (Some(pie), Some(shared), _) if pie > shared => {
+                return Err(output_type(pie, "conflicts with an earlier `-shared`"))
+            }
+            (Some(_), Some(shared), _) => {
+                return Err(output_type(shared, "conflicts with an earlier `-pie`"))
+            }
+            (_, None, Some((position, _))) => {
+                return Err(output_type(position, "only applies to shared objects"))
+            }
         };
 
-        let output_file = output_file
-            .into_inner()
-            .unwrap_or_else(|| PathBuf::from("a.out"));
+        let output_file = output_file.unwrap_or_else(|| PathBuf::from("a.out"));
 
-        Input {
-            input_files,
+        Ok(Input {
+            inputs,
             library_paths,
-            libraries,
             output_file,
             output_options,
             eh_frame_header,
             z_keywords,
-        }
+            map_file,
+        })
     }
 }
 
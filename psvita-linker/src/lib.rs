@@ -1,2 +1,3 @@
 pub mod codegen;
 pub mod input;
+pub mod link;
@@ -0,0 +1,88 @@
+//! Static archives, whose members are loaded only when they are needed.
+
+use super::{LinkError, ObjectFile};
+use log::debug;
+use object::read::archive::ArchiveFile;
+use std::collections::{hash_map::Entry, HashMap};
+
+#[derive(Debug)]
+pub struct Archive {
+    pub name: String,
+    members: Vec<Member>,
+    /// Global symbols defined by members, mapped to the index of the first defining member.
+    definitions: HashMap<String, usize>,
+}
+
+#[derive(Debug)]
+struct Member {
+    name: String,
+    /// Taken out once the member is loaded.
+    object: Option<ObjectFile>,
+}
+
+impl Archive {
+    /// Parse every ELF member of the archive, skipping `lib.rmeta` and other foreign members.
+    pub fn parse(
+        name: String,
+        data: &[u8],
+        gc_sections: bool,
+        as_needed: bool,
+    ) -> Result<Self, LinkError> {
+        let archive = ArchiveFile::parse(data).map_err(LinkError::parse(&name))?;
+
+        let mut members = Vec::new();
+        let mut definitions = HashMap::new();
+        for member in archive.members() {
+            let member = member.map_err(LinkError::parse(&name))?;
+            let member_name = String::from_utf8_lossy(member.name()).into_owned();
+            let member_data = member.data(data).map_err(LinkError::parse(&name))?;
+            if !member_data.starts_with(&object::elf::ELFMAG) {
+                debug!("{}: skipping non-ELF member `{}`", name, member_name);
+                continue;
+            }
+
+            let object = ObjectFile::parse(
+                format!("{}({})", name, member_name),
+                member_data,
+                gc_sections,
+                as_needed,
+            )?;
+            for symbol in &object.symbols {
+                if !symbol.is_local() && !symbol.is_undefined() && !symbol.name.is_empty() {
+                    if let Entry::Vacant(entry) = definitions.entry(symbol.name.clone()) {
+                        entry.insert(members.len());
+                    }
+                }
+            }
+            members.push(Member {
+                name: member_name,
+                object: Some(object),
+            });
+        }
+
+        Ok(Archive {
+            name,
+            members,
+            definitions,
+        })
+    }
+
+    /// Index of a not yet loaded member which defines the symbol.
+    pub fn member_defining(&self, symbol: &str) -> Option<usize> {
+        let &index = self.definitions.get(symbol)?;
+        self.members[index].object.as_ref().map(|_| index)
+    }
+
+    pub fn member_count(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn member_name(&self, index: usize) -> &str {
+        &self.members[index].name
+    }
+
+    /// Take the member out of the archive, `None` if it was already loaded.
+    pub fn take_member(&mut self, index: usize) -> Option<ObjectFile> {
+        self.members[index].object.take()
+    }
+}
@@ -0,0 +1,215 @@
+//! Linking against `.vso` modules, the unencrypted SCE ELFs of dynamic libraries.
+//!
+//! A module is read through the `SceModuleExport` tables its module info points at. Exports
+//! only carry NIDs, so a symbol binds to an export when the NID of its name matches. Only
+//! symbols which are undefined when the module is loaded can be matched, the same way
+//! archive members are picked. Matched symbols get entries like those of vitasdk stub
+//! libraries, from which [`Linker::import_libraries`] builds the import tables.
+
+use super::{
+    imports::{FUNCTION_STUBS_PREFIX, VARIABLE_STUBS_PREFIX},
+    object_file::FileHeader32,
+    synthetic::SyntheticObject,
+    LinkError, Linker,
+};
+use crate::codegen::{VitaEndian, VITA_ENDIAN};
+use bytemuck::{bytes_of_mut, Pod};
+use log::debug;
+use object::{elf, read::elf::FileHeader};
+use psvita_sce_types::{
+    elf::{ENTRY_SEGMENT_SHIFT, ET_SCE_EXEC, ET_SCE_RELEXEC},
+    module_exports::{SceModuleExportCommon, SceModuleExportSized1C, SceModuleExportSized20},
+    module_info::SceModuleInfo,
+    nid::Nid,
+    SceLibraryAttribute,
+};
+use std::{collections::HashSet, convert::TryInto, mem};
+
+type ProgramHeader32 = elf::ProgramHeader32<VitaEndian>;
+
+/// Library exported by a module.
+#[derive(Debug)]
+struct ModuleLibrary {
+    name: String,
+    nid: Nid,
+    functions: Vec<Nid>,
+    variables: Vec<Nid>,
+}
+
+/// Whether the ELF `data` is a linked SCE module rather than a relocatable object.
+pub fn is_module(data: &[u8]) -> bool {
+    FileHeader32::parse(data)
+        .is_ok_and(|header| matches!(header.e_type(VITA_ENDIAN), ET_SCE_EXEC | ET_SCE_RELEXEC))
+}
+
+impl Linker<'_> {
+    /// Import the exports of the module `data` which define still undefined symbols.
+    ///
+    /// With `as_needed`, a library is only imported if a relocation refers to one of them.
+    /// Otherwise every library of the module is imported, even without any of its exports,
+    /// so that the module gets loaded along with the output.
+    pub fn load_module(
+        &mut self,
+        name: String,
+        data: &[u8],
+        as_needed: bool,
+    ) -> Result<(), LinkError> {
+        let libraries = module_libraries(data).ok_or_else(|| LinkError::UnsupportedInput {
+            name: name.clone(),
+            reason: "malformed module exports",
+        })?;
+
+        let mut undefined: Vec<_> = self
+            .symbols
+            .undefined()
+            .map(|(symbol, _)| (Nid::generate(symbol.as_bytes()), symbol.to_owned()))
+            .collect();
+        undefined.sort_by(|a, b| a.1.cmp(&b.1));
+
+        let mut object = SyntheticObject::new(&name);
+        let mut defined = HashSet::new();
+        for library in &libraries {
+            for (prefix, nids, flags, kind) in [
+                (
+                    FUNCTION_STUBS_PREFIX,
+                    &library.functions,
+                    elf::SHF_EXECINSTR,
+                    elf::STT_FUNC,
+                ),
+                (
+                    VARIABLE_STUBS_PREFIX,
+                    &library.variables,
+                    0,
+                    elf::STT_OBJECT,
+                ),
+            ] {
+                let wanted: Vec<_> = undefined
+                    .iter()
+                    .filter(|(nid, symbol)| nids.contains(nid) && !defined.contains(symbol))
+                    .collect();
+                if wanted.is_empty() {
+                    continue;
+                }
+                let section = object.add_section(&format!("{}.{}", prefix, library.name), flags, 4);
+                for (nid, symbol) in wanted {
+                    let entry: Vec<u8> = [0, library.nid.0, nid.0, 0]
+                        .iter()
+                        .flat_map(|word| word.to_le_bytes())
+                        .collect();
+                    let offset = object.append(section, &entry, 4);
+                    object.define_global(symbol, section, offset, entry.len() as u32, kind);
+                    defined.insert(symbol.clone());
+                    debug!("{}: `{}` imported from `{}`", name, symbol, library.name);
+                }
+            }
+        }
+
+        if !as_needed {
+            self.needed_libraries.extend(
+                libraries
+                    .iter()
+                    .map(|library| (library.name.clone(), library.nid)),
+            );
+        }
+        if defined.is_empty() {
+            debug!("{}: no undefined symbol is exported", name);
+            return Ok(());
+        }
+        let mut object = object.into_object();
+        object.as_needed = as_needed;
+        self.add_object(object)?;
+        Ok(())
+    }
+}
+
+/// Named libraries exported by a module, `None` if the export tables cannot be read.
+fn module_libraries(data: &[u8]) -> Option<Vec<ModuleLibrary>> {
+    let header = FileHeader32::parse(data).ok()?;
+    let segments = header.program_headers(VITA_ENDIAN, data).ok()?;
+
+    // `e_entry` holds the segment of the module info and its offset in there
+    let entry = header.e_entry.get(VITA_ENDIAN);
+    let segment = segments.get((entry >> ENTRY_SEGMENT_SHIFT) as usize)?;
+    let base = segment.p_vaddr.get(VITA_ENDIAN);
+    let info: SceModuleInfo = read_pod(
+        segments,
+        data,
+        base.checked_add(entry & ((1 << ENTRY_SEGMENT_SHIFT) - 1))?,
+    )?;
+
+    let mut libraries = Vec::new();
+    let mut at = base.checked_add(info.public_api.exports.top.0)?;
+    let end = base.checked_add(info.public_api.exports.bottom.0)?;
+    while at < end {
+        let common: SceModuleExportCommon = read_pod(segments, data, at)?;
+        let (libname_nid, libname, nid_table) = match common.size as usize {
+            size if size == mem::size_of::<SceModuleExportSized20>() => {
+                let export: SceModuleExportSized20 = read_pod(segments, data, at)?;
+                (
+                    Some(export.libname_nid),
+                    export.libname.0,
+                    export.nid_table.0,
+                )
+            }
+            size if size == mem::size_of::<SceModuleExportSized1C>() => {
+                let export: SceModuleExportSized1C = read_pod(segments, data, at)?;
+                (None, export.libname.0, export.nid_table.0)
+            }
+            _ => return None,
+        };
+        at += common.size as u32;
+
+        // the NONAME export only holds the module entry points
+        let attribute = SceLibraryAttribute::from_bits_truncate(common.attribute);
+        if attribute.contains(SceLibraryAttribute::MAIN_EXPORT) || libname == 0 {
+            continue;
+        }
+        let name = read_c_str(segments, data, libname)?;
+        let nfunc = common.nfunc as usize;
+        let nvar = common.nvar as usize;
+        let nids: Vec<_> = read(segments, data, nid_table, (nfunc + nvar) * 4)?
+            .chunks_exact(4)
+            .map(|nid| Nid(u32::from_le_bytes(nid.try_into().unwrap())))
+            .collect();
+        libraries.push(ModuleLibrary {
+            nid: libname_nid.unwrap_or_else(|| Nid::generate(name.as_bytes())),
+            name,
+            functions: nids[..nfunc].to_vec(),
+            variables: nids[nfunc..].to_vec(),
+        });
+    }
+    Some(libraries)
+}
+
+/// File contents at a virtual address, which have to be inside of a single segment.
+fn read<'d>(
+    segments: &[ProgramHeader32],
+    data: &'d [u8],
+    address: u32,
+    len: usize,
+) -> Option<&'d [u8]> {
+    segments.iter().find_map(|segment| {
+        let offset = address.checked_sub(segment.p_vaddr.get(VITA_ENDIAN))? as usize;
+        if offset.checked_add(len)? > segment.p_filesz.get(VITA_ENDIAN) as usize {
+            return None;
+        }
+        let start = segment.p_offset.get(VITA_ENDIAN) as usize + offset;
+        data.get(start..start + len)
+    })
+}
+
+fn read_pod<T: Pod>(segments: &[ProgramHeader32], data: &[u8], address: u32) -> Option<T> {
+    let mut value = T::zeroed();
+    bytes_of_mut(&mut value).copy_from_slice(read(segments, data, address, mem::size_of::<T>())?);
+    Some(value)
+}
+
+fn read_c_str(segments: &[ProgramHeader32], data: &[u8], address: u32) -> Option<String> {
+    let mut bytes = Vec::new();
+    loop {
+        match read(segments, data, address.checked_add(bytes.len() as u32)?, 1)?[0] {
+            0 => return Some(String::from_utf8_lossy(&bytes).into_owned()),
+            byte => bytes.push(byte),
+        }
+    }
+}
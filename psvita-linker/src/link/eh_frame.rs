@@ -0,0 +1,445 @@
+//! `.eh_frame_hdr`, the binary search table over `.eh_frame` used by DWARF unwinders.
+//!
+//! The header consists of a version byte, the encodings of the following fields, a pointer
+//! to `.eh_frame`, the number of FDEs and a table of initial locations and FDE addresses
+//! sorted by location, both relative to the start of the header.
+//!
+//! FDEs of code which `--gc-sections` or COMDAT deduplication discarded are dropped from
+//! the `.eh_frame` inputs, so neither the frames nor the table describe code that is gone.
+//!
+//! The loader does not know `PT_GNU_EH_FRAME`, so only the debug companion has it. Unwinders
+//! in the shipped image find the header through [`EH_FRAME_HDR_SYMBOL`] instead, the way
+//! statically linked programs without `dl_iterate_phdr` do.
+
+use super::{
+    synthetic::SyntheticObject, InputSection, Layout, LinkError, Linker, ObjectId, SectionId,
+    SymbolSection,
+};
+use log::{debug, warn};
+use object::elf;
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryInto,
+};
+
+/// Section of the call frame information in the `.eh_frame` format.
+pub const EH_FRAME_SECTION: &str = ".eh_frame";
+/// Section of the search table, pointed at by `PT_GNU_EH_FRAME`.
+pub const EH_FRAME_HDR_SECTION: &str = ".eh_frame_hdr";
+/// Symbol defined at the start of the search table.
+pub const EH_FRAME_HDR_SYMBOL: &str = "__GNU_EH_FRAME_HDR";
+
+const DW_EH_PE_ABSPTR: u8 = 0x00;
+const DW_EH_PE_UDATA2: u8 = 0x02;
+const DW_EH_PE_UDATA4: u8 = 0x03;
+const DW_EH_PE_SDATA2: u8 = 0x0A;
+const DW_EH_PE_SDATA4: u8 = 0x0B;
+const DW_EH_PE_PCREL: u8 = 0x10;
+const DW_EH_PE_DATAREL: u8 = 0x30;
+const DW_EH_PE_OMIT: u8 = 0xFF;
+
+/// Size of the header preceding the table.
+const HEADER_SIZE: u32 = 12;
+/// Size of a table entry.
+const ENTRY_SIZE: u32 = 8;
+
+impl Linker<'_> {
+    /// Sections which the frames of `live` code refer to, like personality routines and LSDAs.
+    pub(crate) fn eh_frame_references(&self, live: &HashSet<SectionId>) -> Vec<SectionId> {
+        let mut references = Vec::new();
+        for (id, section) in self.eh_frame_sections() {
+            let records = records(&section.data);
+            for fde in records.iter().filter(|r| r.id != 0) {
+                let describes_live_code = self
+                    .fde_target(id.object, section, fde)
+                    .is_none_or(|target| live.contains(&target));
+                if !describes_live_code {
+                    continue;
+                }
+                let cie = fde
+                    .cie()
+                    .and_then(|cie| records.iter().find(|r| r.start() == cie));
+                for record in std::iter::once(fde).chain(cie) {
+                    references.extend(
+                        section
+                            .relocations
+                            .iter()
+                            .filter(|rel| record.contains(rel.offset as usize))
+                            .filter_map(|rel| self.symbol_section(id.object, rel.symbol)),
+                    );
+                }
+            }
+        }
+        references
+    }
+
+    /// Remove the FDEs whose code is no longer part of the link, with their relocations.
+    pub fn prune_eh_frames(&mut self) {
+        let frames: Vec<_> = self.eh_frame_sections().map(|(id, _)| id).collect();
+        for id in frames {
+            let section = self.objects[id.object.0].section(id.index).unwrap();
+            let dead: Vec<_> = records(&section.data)
+                .into_iter()
+                .filter(|r| r.id != 0)
+                .filter(|fde| {
+                    self.fde_target(id.object, section, fde)
+                        .is_some_and(|target| {
+                            self.objects[target.object.0]
+                                .section(target.index)
+                                .is_none()
+                        })
+                })
+                .map(|fde| (fde.start(), fde.end))
+                .collect();
+            if dead.is_empty() {
+                continue;
+            }
+            debug!(
+                "{}: dropping {} FDEs of discarded sections",
+                self.objects[id.object.0].name,
+                dead.len()
+            );
+
+            // offsets past the dropped records move down by their size
+            let moved = |offset: usize| {
+                offset
+                    - dead
+                        .iter()
+                        .filter(|(start, _)| *start < offset)
+                        .map(|(start, end)| end.min(&offset) - start)
+                        .sum::<usize>()
+            };
+            let is_dead = |offset: usize| {
+                dead.iter()
+                    .any(|(start, end)| (*start..*end).contains(&offset))
+            };
+
+            let object = &mut self.objects[id.object.0];
+            let section = object.sections[id.index].as_mut().unwrap();
+            let mut data = Vec::with_capacity(section.data.len());
+            let mut at = 0;
+            for (start, end) in &dead {
+                data.extend_from_slice(&section.data[at..*start]);
+                at = *end;
+            }
+            data.extend_from_slice(&section.data[at..]);
+            // CIE pointers are relative, dropped FDEs between a CIE and its users shorten them
+            for fde in records(&section.data).iter().filter(|r| r.id != 0) {
+                if let (false, Some(cie)) = (is_dead(fde.id_offset), fde.cie()) {
+                    let at = moved(fde.id_offset);
+                    let id = (at - moved(cie)) as u32;
+                    data[at..at + 4].copy_from_slice(&id.to_le_bytes());
+                }
+            }
+
+            section
+                .relocations
+                .retain(|rel| !is_dead(rel.offset as usize));
+            for rel in &mut section.relocations {
+                rel.offset = moved(rel.offset as usize) as u32;
+            }
+            section.size = data.len() as u32;
+            section.data = data;
+            for symbol in &mut object.symbols {
+                if symbol.section == SymbolSection::Section(id.index) {
+                    symbol.value = moved(symbol.value as usize) as u32;
+                }
+            }
+        }
+    }
+
+    /// `.eh_frame` input sections of the link.
+    fn eh_frame_sections(&self) -> impl Iterator<Item = (SectionId, &InputSection)> + '_ {
+        self.objects.iter().enumerate().flat_map(|(object, file)| {
+            file.sections
+                .iter()
+                .enumerate()
+                .filter_map(move |(index, section)| {
+                    let section = section.as_ref()?;
+                    let id = SectionId {
+                        object: ObjectId(object),
+                        index,
+                    };
+                    Some((id, section)).filter(|_| section.name == EH_FRAME_SECTION)
+                })
+        })
+    }
+
+    /// Section holding the code an FDE describes, found by the relocation of its location.
+    fn fde_target(
+        &self,
+        object: ObjectId,
+        section: &InputSection,
+        fde: &Record,
+    ) -> Option<SectionId> {
+        let location = (fde.id_offset + 4) as u32;
+        let rel = section
+            .relocations
+            .iter()
+            .find(|rel| rel.offset == location)?;
+        self.symbol_section(object, rel.symbol)
+    }
+
+    /// Reserve the header with an entry for every FDE of the inputs, if it was requested.
+    ///
+    /// [`EH_FRAME_HDR_SYMBOL`] is defined at its start, unless an input defines it already.
+    pub fn reserve_eh_frame_hdr(&mut self) -> Result<(), LinkError> {
+        if !self.input.eh_frame_header {
+            return Ok(());
+        }
+        let frames: Vec<_> = self
+            .objects
+            .iter()
+            .flat_map(|o| o.sections.iter().flatten())
+            .filter(|s| s.name == EH_FRAME_SECTION)
+            .collect();
+        if frames.is_empty() {
+            return Ok(());
+        }
+        let fdes = frames
+            .iter()
+            .flat_map(|s| records(&s.data))
+            .filter(|r| r.id != 0)
+            .count() as u32;
+
+        let mut object = SyntheticObject::new("<eh_frame_hdr>");
+        let section = object.add_section(EH_FRAME_HDR_SECTION, 0, 4);
+        let size = HEADER_SIZE + fdes * ENTRY_SIZE;
+        object.append(section, &vec![0; size as usize], 4);
+        if self.symbols.definition(EH_FRAME_HDR_SYMBOL).is_none() {
+            object.define_global(EH_FRAME_HDR_SYMBOL, section, 0, size, elf::STT_OBJECT);
+        }
+        let object = self.add_object(object.into_object())?;
+        self.eh_frame_hdr = Some(SectionId {
+            object,
+            index: section,
+        });
+        Ok(())
+    }
+
+    /// Fill the header from the relocated `.eh_frame`.
+    ///
+    /// FDEs of discarded code are left out of the table.
+    pub fn write_eh_frame_hdr(&self, layout: &mut Layout) -> Result<(), LinkError> {
+        let (hdr_index, hdr_offset) = match self.eh_frame_hdr.and_then(|id| layout.placement(id)) {
+            Some(placement) => placement,
+            None => return Ok(()),
+        };
+        let hdr_address = layout.sections[hdr_index].address + hdr_offset;
+        let (frame_address, table) = match layout.find_section(EH_FRAME_SECTION) {
+            Some(index) => {
+                let frame = &layout.sections[index];
+                (
+                    frame.address,
+                    search_table(layout, &frame.data, frame.address),
+                )
+            }
+            None => (hdr_address, Vec::new()),
+        };
+
+        let mut data = vec![
+            1,
+            DW_EH_PE_PCREL | DW_EH_PE_SDATA4,
+            DW_EH_PE_UDATA4,
+            DW_EH_PE_DATAREL | DW_EH_PE_SDATA4,
+        ];
+        data.extend_from_slice(&frame_address.wrapping_sub(hdr_address + 4).to_le_bytes());
+        data.extend_from_slice(&(table.len() as u32).to_le_bytes());
+        for (location, fde) in &table {
+            data.extend_from_slice(&location.wrapping_sub(hdr_address).to_le_bytes());
+            data.extend_from_slice(&fde.wrapping_sub(hdr_address).to_le_bytes());
+        }
+        debug!("{} FDEs in {}", table.len(), EH_FRAME_HDR_SECTION);
+
+        let output = &mut layout.sections[hdr_index];
+        let start = hdr_offset as usize;
+        let reserved = &mut output.data[start..];
+        if data.len() > reserved.len() {
+            return Err(LinkError::ReserveExceeded {
+                section: EH_FRAME_HDR_SECTION,
+                needed: data.len() as u32,
+                reserved: reserved.len() as u32,
+            });
+        }
+        reserved[..data.len()].copy_from_slice(&data);
+        // the header is alone in its output section, so unused entries can go
+        output.size = start as u32 + data.len() as u32;
+        output.data.truncate(output.size as usize);
+        Ok(())
+    }
+}
+
+/// CIE or FDE in `.eh_frame` data.
+struct Record {
+    /// Offset of the CIE pointer, or of the zero id of a CIE.
+    id_offset: usize,
+    id: u32,
+    /// Offset following the record.
+    end: usize,
+}
+
+impl Record {
+    /// Offset of the length which starts the record.
+    fn start(&self) -> usize {
+        self.id_offset - 4
+    }
+
+    /// Start of the CIE of an FDE.
+    fn cie(&self) -> Option<usize> {
+        self.id_offset.checked_sub(self.id as usize)
+    }
+
+    fn contains(&self, offset: usize) -> bool {
+        (self.start()..self.end).contains(&offset)
+    }
+}
+
+/// Records of `.eh_frame` data, skipping zero terminators.
+fn records(data: &[u8]) -> Vec<Record> {
+    let mut records = Vec::new();
+    let mut offset = 0;
+    while let Some(length) = read_u32(data, offset) {
+        if length == 0 {
+            offset += 4;
+            continue;
+        }
+        // 64-bit lengths have no place in a 32-bit image
+        let end = match (offset + 4).checked_add(length as usize) {
+            Some(end) if length != u32::MAX && end <= data.len() => end,
+            _ => break,
+        };
+        if let Some(id) = read_u32(data, offset + 4) {
+            records.push(Record {
+                id_offset: offset + 4,
+                id,
+                end,
+            });
+        }
+        offset = end;
+    }
+    records
+}
+
+/// Initial locations and addresses of the FDEs describing code, sorted by location.
+fn search_table(layout: &Layout, data: &[u8], address: u32) -> Vec<(u32, u32)> {
+    let is_code = |location: u32| {
+        layout.sections.iter().any(|s| {
+            s.flags & elf::SHF_EXECINSTR != 0 && (s.address..s.address + s.size).contains(&location)
+        })
+    };
+
+    let mut encodings = HashMap::new();
+    let mut table = Vec::new();
+    for record in records(data).iter().filter(|r| r.id != 0) {
+        let cie = match record.id_offset.checked_sub(record.id as usize) {
+            Some(cie) => cie,
+            None => continue,
+        };
+        let encoding = *encodings
+            .entry(cie)
+            .or_insert_with(|| fde_encoding(data, cie));
+        let at = record.id_offset + 4;
+        let location =
+            encoding.and_then(|encoding| read_pointer(data, at, encoding, address + at as u32));
+        match location {
+            Some((location, _)) if is_code(location) => {
+                table.push((location, address + record.id_offset as u32 - 4))
+            }
+            Some(_) => (),
+            None => warn!(
+                "cannot decode the FDE at {:#x}",
+                address + record.id_offset as u32 - 4
+            ),
+        }
+    }
+    table.sort_unstable();
+    table
+}
+
+/// Encoding of the initial location of FDEs which use the CIE at `offset`.
+fn fde_encoding(data: &[u8], offset: usize) -> Option<u8> {
+    // past the length and the zero id
+    let mut at = offset + 8;
+    let version = *data.get(at)?;
+    at += 1;
+    let augmentation_end = at + data.get(at..)?.iter().position(|&b| b == 0)?;
+    let augmentation = &data[at..augmentation_end];
+    at = augmentation_end + 1;
+    // code and data alignment factors, return address register
+    at = read_leb128(data, at)?.1;
+    at = read_leb128(data, at)?.1;
+    at = if version == 1 {
+        at + 1
+    } else {
+        read_leb128(data, at)?.1
+    };
+
+    if augmentation.first() != Some(&b'z') {
+        return Some(DW_EH_PE_ABSPTR);
+    }
+    at = read_leb128(data, at)?.1;
+    for character in &augmentation[1..] {
+        match character {
+            b'R' => return data.get(at).copied(),
+            b'P' => {
+                let encoding = *data.get(at)?;
+                // the personality may be indirect, which only matters for its value
+                at = read_pointer(data, at + 1, encoding & 0x7F, 0)?.1;
+            }
+            b'L' => at += 1,
+            _ => (),
+        }
+    }
+    Some(DW_EH_PE_ABSPTR)
+}
+
+/// Read a pointer in a `DW_EH_PE_*` encoding, with `address` being the address of `at`.
+///
+/// Returns the pointer and the offset following it.
+fn read_pointer(data: &[u8], at: usize, encoding: u8, address: u32) -> Option<(u32, usize)> {
+    if encoding == DW_EH_PE_OMIT {
+        return None;
+    }
+    let (value, end) = match encoding & 0x0F {
+        DW_EH_PE_ABSPTR | DW_EH_PE_UDATA4 | DW_EH_PE_SDATA4 => (read_u32(data, at)?, at + 4),
+        DW_EH_PE_UDATA2 => (read_u16(data, at)? as u32, at + 2),
+        DW_EH_PE_SDATA2 => (read_u16(data, at)? as i16 as u32, at + 2),
+        _ => return None,
+    };
+    match encoding & 0x70 {
+        0 => Some((value, end)),
+        DW_EH_PE_PCREL => Some((value.wrapping_add(address), end)),
+        _ => None,
+    }
+}
+
+/// Read an unsigned LEB128 number, returns it with the offset following it.
+///
+/// Signed numbers are not sign extended, which does not matter for skipping them.
+fn read_leb128(data: &[u8], mut at: usize) -> Option<(u64, usize)> {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = *data.get(at)?;
+        at += 1;
+        if shift < 64 {
+            value |= ((byte & 0x7F) as u64) << shift;
+        }
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Some((value, at));
+        }
+    }
+}
+
+fn read_u32(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(at..at + 4)?.try_into().unwrap(),
+    ))
+}
+
+fn read_u16(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(at..at + 2)?.try_into().unwrap(),
+    ))
+}
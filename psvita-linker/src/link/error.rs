@@ -0,0 +1,184 @@
+use crate::codegen::verification::VerifyHeaderError;
+use std::{fmt, io, path::PathBuf};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum LinkError {
+    #[error("cannot read `{}`: {source}", path.display())]
+    Read { path: PathBuf, source: io::Error },
+
+    #[error("cannot write `{}`: {source}", path.display())]
+    Write { path: PathBuf, source: io::Error },
+
+    #[error("cannot parse `{name}`: {source}")]
+    Parse {
+        name: String,
+        source: object::read::Error,
+    },
+
+    #[error("`{name}`: {source}")]
+    BadHeader {
+        name: String,
+        source: VerifyHeaderError,
+    },
+
+    #[error("`{name}`: unsupported input file ({reason})")]
+    UnsupportedInput { name: String, reason: &'static str },
+
+    #[error("cannot find library `{library}`, tried: {}", display_paths(tried))]
+    LibraryNotFound {
+        library: String,
+        tried: Vec<PathBuf>,
+    },
+
+    #[error("{}", display_undefined(.0))]
+    UndefinedSymbols(Vec<UndefinedReference>),
+
+    #[error("{}", display_duplicates(.0))]
+    DuplicateSymbols(Vec<DuplicateDefinition>),
+
+    #[error(
+        "`{object}`: relocation type {r_type} against `{symbol}` at `{section}`+{offset:#x} \
+         is out of range"
+    )]
+    RelocationOverflow {
+        object: String,
+        section: String,
+        offset: u32,
+        symbol: String,
+        r_type: u32,
+    },
+
+    #[error(
+        "`{object}`: relocation type {r_type} at `{section}`+{offset:#x} does not fit into \
+         the section"
+    )]
+    RelocationOutOfBounds {
+        object: String,
+        section: String,
+        offset: u32,
+        r_type: u32,
+    },
+
+    #[error("`{section}` needs {needed} bytes, only {reserved} were reserved before the layout")]
+    ReserveExceeded {
+        section: &'static str,
+        needed: u32,
+        reserved: u32,
+    },
+
+    #[error("`{object}`: unsupported relocation type {r_type} in section `{section}`")]
+    UnsupportedRelocation {
+        object: String,
+        section: String,
+        r_type: u32,
+    },
+}
+
+impl LinkError {
+    pub(crate) fn parse(name: &str) -> impl FnOnce(object::read::Error) -> LinkError + '_ {
+        move |source| LinkError::Parse {
+            name: name.to_owned(),
+            source,
+        }
+    }
+}
+
+/// Object, and the section if there is one, which defines or references a symbol.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SymbolLocation {
+    pub object: String,
+    pub section: Option<String>,
+}
+
+impl fmt::Display for SymbolLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}`", self.object)?;
+        match &self.section {
+            Some(section) => write!(f, " section `{}`", section),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Reference to a symbol which nothing defines.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct UndefinedReference {
+    /// Symbol name as it appears in the object.
+    pub symbol: String,
+    pub location: SymbolLocation,
+}
+
+impl fmt::Display for UndefinedReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "undefined symbol `{}` referenced in {}",
+            demangle(&self.symbol),
+            self.location
+        )
+    }
+}
+
+impl UndefinedReference {
+    /// Suggestion for symbols which are usually imported from a system module.
+    pub fn hint(&self) -> Option<String> {
+        let name = self.symbol.trim_start_matches('_');
+        if name.starts_with("sce") {
+            Some(format!(
+                "`{}` looks like a system API, it is imported from a stub library, \
+                 check that a `-l` option for the library exporting it is present",
+                self.symbol
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+/// Strong definition of a symbol which an earlier object defines already.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateDefinition {
+    pub symbol: String,
+    pub first: SymbolLocation,
+    pub second: SymbolLocation,
+}
+
+impl fmt::Display for DuplicateDefinition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "duplicate symbol `{}`\n  first defined in {}\n  also defined in {}",
+            demangle(&self.symbol),
+            self.first,
+            self.second
+        )
+    }
+}
+
+/// Readable name of a symbol, Rust symbols are demangled without their hash.
+pub fn demangle(symbol: &str) -> String {
+    format!("{:#}", rustc_demangle::demangle(symbol))
+}
+
+fn display_undefined(references: &[UndefinedReference]) -> String {
+    let mut lines: Vec<_> = references.iter().map(|r| r.to_string()).collect();
+    let mut hinted = Vec::new();
+    for hint in references.iter().filter_map(|r| r.hint()) {
+        if !hinted.contains(&hint) {
+            hinted.push(hint);
+        }
+    }
+    lines.extend(hinted.into_iter().map(|hint| format!("  hint: {}", hint)));
+    lines.join("\n")
+}
+
+fn display_duplicates(duplicates: &[DuplicateDefinition]) -> String {
+    let lines: Vec<_> = duplicates.iter().map(|d| d.to_string()).collect();
+    lines.join("\n")
+}
+
+fn display_paths(paths: &[PathBuf]) -> String {
+    let paths: Vec<_> = paths.iter().map(|p| p.display().to_string()).collect();
+    paths.join(", ")
+}
@@ -0,0 +1,203 @@
+//! `.ARM.exidx`, the index which the unwinder searches for the entry describing an address.
+//!
+//! Each 8 byte entry holds a `PREL31` offset to the start of the code it describes and
+//! either [`EXIDX_CANTUNWIND`], an inline unwinding program with the top bit set, or a
+//! `PREL31` offset into `.ARM.extab`. An entry covers the code up to the next entry, so the
+//! table has to be sorted by address and the code without unwinding information needs
+//! entries of its own.
+
+use super::{
+    relocation::sign_extend, synthetic::SyntheticObject, Layout, LinkError, Linker, ObjectId,
+    SectionId,
+};
+use log::debug;
+use object::elf;
+use std::{collections::HashSet, convert::TryInto};
+
+/// Output section of the unwind index.
+pub const EXIDX_SECTION: &str = ".ARM.exidx";
+
+/// Second word of entries for code which cannot be unwound through.
+pub const EXIDX_CANTUNWIND: u32 = 1;
+
+/// Size of an index entry.
+const ENTRY_SIZE: u32 = 8;
+
+/// How the code of an entry unwinds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Unwinding {
+    CantUnwind,
+    /// Unwinding program in the entry itself.
+    Inline(u32),
+    /// Address of the unwinding program in `.ARM.extab`.
+    Table(u32),
+}
+
+impl Linker<'_> {
+    /// Reserve space for the entries which [`Linker::sort_exidx`] adds to the index.
+    ///
+    /// Every executable section without an index entry may need one, as may the end of the
+    /// code. Unused space is cut off the index once it is sorted. Veneers are only known once
+    /// the code is laid out, [`Linker::grow_exidx_reserve`] makes room for them.
+    pub fn reserve_exidx(&mut self) -> Result<(), LinkError> {
+        let entries = match self.exidx_entries_needed() {
+            Some(entries) => entries,
+            None => return Ok(()),
+        };
+        let mut object = SyntheticObject::new("<exidx>");
+        let section = object.add_section(EXIDX_SECTION, 0, 4);
+        object.append(section, &vec![0; (entries * ENTRY_SIZE) as usize], 4);
+        let object = self.add_object(object.into_object())?;
+        self.exidx_reserve = Some(SectionId {
+            object,
+            index: section,
+        });
+        Ok(())
+    }
+
+    /// Grow the reserved space to cover sections added since it was reserved, like veneers.
+    ///
+    /// Returns whether it grew, in which case the layout has to be redone.
+    pub fn grow_exidx_reserve(&mut self) -> bool {
+        let (id, entries) = match (self.exidx_reserve, self.exidx_entries_needed()) {
+            (Some(id), Some(entries)) => (id, entries),
+            _ => return false,
+        };
+        let section = self.objects[id.object.0].sections[id.index]
+            .as_mut()
+            .unwrap();
+        let size = entries * ENTRY_SIZE;
+        if section.size >= size {
+            return false;
+        }
+        section.data.resize(size as usize, 0);
+        section.size = size;
+        true
+    }
+
+    /// Upper bound of the number of entries the sorted index needs on top of those of the
+    /// input sections, `None` if no input section has an index.
+    fn exidx_entries_needed(&self) -> Option<u32> {
+        let mut described = HashSet::new();
+        for (object_index, object) in self.objects.iter().enumerate() {
+            for section in object.sections.iter().flatten() {
+                if is_exidx(&section.name, section.sh_type) {
+                    described.insert(SectionId {
+                        object: ObjectId(object_index),
+                        index: section.link as usize,
+                    });
+                }
+            }
+        }
+        if described.is_empty() {
+            return None;
+        }
+
+        let mut entries = 2;
+        for (object_index, object) in self.objects.iter().enumerate() {
+            for (index, section) in object.sections.iter().enumerate() {
+                let id = SectionId {
+                    object: ObjectId(object_index),
+                    index,
+                };
+                if section
+                    .as_ref()
+                    .is_some_and(|s| s.is_alloc() && s.flags & elf::SHF_EXECINSTR != 0)
+                    && !described.contains(&id)
+                {
+                    entries += 1;
+                }
+            }
+        }
+        Some(entries)
+    }
+
+    /// Sort the relocated index by address, terminate code without unwinding information
+    /// and merge adjacent entries which unwind the same way.
+    pub fn sort_exidx(&self, layout: &mut Layout) -> Result<(), LinkError> {
+        let (output_index, reserved_at) =
+            match self.exidx_reserve.and_then(|id| layout.placement(id)) {
+                Some(placement) => placement,
+                None => return Ok(()),
+            };
+
+        let output = &layout.sections[output_index];
+        let mut entries = Vec::new();
+        for offset in (0..reserved_at).step_by(ENTRY_SIZE as usize) {
+            let place = output.address + offset;
+            let word = |at: u32| {
+                let at = at as usize;
+                u32::from_le_bytes(output.data[at..at + 4].try_into().unwrap())
+            };
+            let (function, unwind) = (word(offset), word(offset + 4));
+            let function = place.wrapping_add(sign_extend(function, 31));
+            let unwind = if unwind == EXIDX_CANTUNWIND {
+                Unwinding::CantUnwind
+            } else if unwind & 0x8000_0000 != 0 {
+                Unwinding::Inline(unwind)
+            } else {
+                Unwinding::Table((place + 4).wrapping_add(sign_extend(unwind, 31)))
+            };
+            entries.push((function, unwind));
+        }
+        entries.sort_by_key(|&(function, _)| function);
+
+        // code without entries would be attributed to the function before it
+        let mut code_end = 0;
+        let mut terminators = Vec::new();
+        for output in layout
+            .sections
+            .iter()
+            .filter(|s| s.flags & elf::SHF_EXECINSTR != 0)
+        {
+            for &(id, offset) in &output.inputs {
+                let size = self.objects[id.object.0].section(id.index).unwrap().size;
+                let start = output.address + offset;
+                let end = start + size;
+                let first = entries.partition_point(|&(function, _)| function < start);
+                if size != 0 && entries.get(first).is_none_or(|&(f, _)| f >= end) {
+                    terminators.push((start, Unwinding::CantUnwind));
+                }
+                code_end = code_end.max(end);
+            }
+        }
+        terminators.push((code_end, Unwinding::CantUnwind));
+        entries.extend(terminators);
+        entries.sort_by_key(|&(function, _)| function);
+        entries.dedup_by(|next, previous| next.1 == previous.1);
+
+        let output = &mut layout.sections[output_index];
+        let mut data = Vec::with_capacity(entries.len() * ENTRY_SIZE as usize);
+        for (index, (function, unwind)) in entries.iter().enumerate() {
+            let place = output.address + index as u32 * ENTRY_SIZE;
+            let unwind = match *unwind {
+                Unwinding::CantUnwind => EXIDX_CANTUNWIND,
+                Unwinding::Inline(word) => word,
+                Unwinding::Table(address) => address.wrapping_sub(place + 4) & 0x7FFF_FFFF,
+            };
+            data.extend_from_slice(&(function.wrapping_sub(place) & 0x7FFF_FFFF).to_le_bytes());
+            data.extend_from_slice(&unwind.to_le_bytes());
+        }
+        if data.len() > output.size as usize {
+            return Err(LinkError::ReserveExceeded {
+                section: EXIDX_SECTION,
+                needed: data.len() as u32,
+                reserved: output.size,
+            });
+        }
+        debug!(
+            "{} unwind index entries, {} bytes of {} used",
+            entries.len(),
+            data.len(),
+            output.size
+        );
+        output.size = data.len() as u32;
+        output.data = data;
+        Ok(())
+    }
+}
+
+/// Whether an input section is a part of the unwind index.
+fn is_exidx(name: &str, sh_type: u32) -> bool {
+    sh_type == elf::SHT_ARM_EXIDX || super::output_section_name(name) == EXIDX_SECTION
+}
@@ -0,0 +1,204 @@
+//! `SceModuleExport` tables, through which the loader finds what a module provides.
+
+use super::{synthetic::SyntheticObject, InputSymbol, Linker};
+use crate::{codegen::ItemType, input::OutputOptions};
+use bytemuck::{bytes_of, Zeroable};
+use log::debug;
+use object::elf;
+use psvita_sce_types::{
+    module_exports::{HashInfo, SceModuleExport},
+    nid::{noname, Nid},
+    SceLibraryAttribute,
+};
+use std::mem;
+
+/// Section holding the `SceModuleExport` structures, which the module info points at.
+pub const EXPORTS_SECTION: &str = ".sceLib.ent";
+/// Section holding the NID and entry tables of the exports.
+pub const EXPORT_TABLES_SECTION: &str = ".sceExport.rodata";
+
+/// Library exported from the output.
+#[derive(Debug)]
+pub struct ExportLibrary {
+    /// `None` for the main NONAME export.
+    pub name: Option<String>,
+    pub attribute: SceLibraryAttribute,
+    pub functions: Vec<Export>,
+    pub variables: Vec<Export>,
+    pub tls: Vec<Export>,
+}
+
+#[derive(Debug)]
+pub struct Export {
+    pub nid: Nid,
+    /// Symbol providing the export, either global or defined by the module info object.
+    pub symbol: String,
+}
+
+impl Linker<'_> {
+    /// Libraries exported from the output, starting with the NONAME export.
+    pub fn export_libraries(&self) -> Vec<ExportLibrary> {
+        let mut libraries = vec![self.main_export()];
+        if let OutputOptions::Shared { .. } = self.input.output_options {
+            let library = self.shared_export();
+            if !library.functions.is_empty()
+                || !library.variables.is_empty()
+                || !library.tls.is_empty()
+            {
+                libraries.push(library);
+            }
+        }
+        libraries
+    }
+
+    /// Library named after the module, with every symbol exported by the version script.
+    fn shared_export(&self) -> ExportLibrary {
+        let mut library = ExportLibrary {
+            name: Some(self.module_name()),
+            attribute: SceLibraryAttribute::AUTO_EXPORT,
+            functions: Vec::new(),
+            variables: Vec::new(),
+            tls: Vec::new(),
+        };
+
+        for (name, global) in self.symbols.iter() {
+            let def = match global.definition {
+                Some(def) => def,
+                None => continue,
+            };
+            // entry points and the module info belong to the NONAME export
+            if noname::try_get_predefined(name.as_bytes()).is_some() || !self.is_exported(name) {
+                continue;
+            }
+            let symbol = &self.objects[def.object.0].symbols[def.index];
+            let export = Export {
+                nid: Nid::generate(name.as_bytes()),
+                symbol: name.to_owned(),
+            };
+            let item_type = item_type(symbol);
+            debug!("exporting {:?} `{}` as {:?}", item_type, name, export.nid);
+            match item_type {
+                ItemType::Function => library.functions.push(export),
+                ItemType::Variable => library.variables.push(export),
+                ItemType::TLS => library.tls.push(export),
+            }
+        }
+
+        // sorted tables let the loader search them
+        library.functions.sort_by_key(|e| e.nid);
+        library.variables.sort_by_key(|e| e.nid);
+        library.tls.sort_by_key(|e| e.nid);
+        library
+    }
+
+    /// The NONAME export with the module entry points and the module info.
+    fn main_export(&self) -> ExportLibrary {
+        let defined = |nid: Nid, name: &str| {
+            self.symbols.definition(name).map(|_| Export {
+                nid,
+                symbol: name.to_owned(),
+            })
+        };
+
+        let functions = self
+            .start_symbol()
+            .map(|name| Export {
+                nid: noname::MODULE_START,
+                symbol: name.to_owned(),
+            })
+            .into_iter()
+            .chain(defined(noname::MODULE_STOP, "module_stop"))
+            .chain(defined(noname::MODULE_EXIT, "module_exit"))
+            .chain(defined(noname::MODULE_BOOTSTART, "module_bootstart"))
+            .collect();
+        let variables = Some(Export {
+            nid: noname::MODULE_INFO,
+            symbol: String::from("module_info"),
+        })
+        .into_iter()
+        .chain(defined(noname::MODULE_PROC_PARAM, "module_proc_param"))
+        .chain(defined(noname::MODULE_SDK_VERSION, "module_sdk_version"))
+        .collect();
+
+        ExportLibrary {
+            name: None,
+            attribute: SceLibraryAttribute::MAIN_EXPORT,
+            functions,
+            variables,
+            tls: Vec::new(),
+        }
+    }
+}
+
+/// Kind of the item a symbol names.
+fn item_type(symbol: &InputSymbol) -> ItemType {
+    match symbol.kind {
+        elf::STT_FUNC | elf::STT_GNU_IFUNC => ItemType::Function,
+        elf::STT_TLS => ItemType::TLS,
+        _ => ItemType::Variable,
+    }
+}
+
+/// Write the export structures and their tables, returns the index of [`EXPORTS_SECTION`].
+pub(crate) fn add_export_tables(
+    object: &mut SyntheticObject,
+    libraries: &[ExportLibrary],
+) -> usize {
+    let exports = object.add_section(EXPORTS_SECTION, 0, 4);
+    let tables = object.add_section(EXPORT_TABLES_SECTION, 0, 4);
+
+    for library in libraries {
+        let entries: Vec<_> = library
+            .functions
+            .iter()
+            .chain(&library.variables)
+            .chain(&library.tls)
+            .collect();
+        let nfunc = library.functions.len() as u16;
+        let nvar = library.variables.len() as u16;
+        let ntls = library.tls.len() as u16;
+
+        let nids: Vec<u8> = entries.iter().flat_map(|e| e.nid.0.to_le_bytes()).collect();
+        let nid_table = object.append(tables, &nids, 4);
+        let entry_table = object.append(tables, &vec![0; entries.len() * 4], 4);
+        for (index, entry) in entries.iter().enumerate() {
+            let symbol = object.reference(&entry.symbol);
+            object.relocate(
+                tables,
+                entry_table + index as u32 * 4,
+                elf::R_ARM_ABS32,
+                symbol,
+            );
+        }
+        let libname = library.name.as_ref().map(|name| {
+            let mut data = name.as_bytes().to_vec();
+            data.push(0);
+            object.append(tables, &data, 1)
+        });
+
+        let mut export = SceModuleExport::zeroed();
+        export.common.size = mem::size_of::<SceModuleExport>() as u8;
+        export.common.version = library.name.is_some() as u16;
+        export.common.attribute = library.attribute.bits();
+        export.common.nfunc = nfunc;
+        export.common.nvar = nvar;
+        export.common.ntls = ntls;
+        export.common.hashinfo = HashInfo::new(nfunc, nvar, ntls);
+        if let Some(name) = &library.name {
+            export.libname_nid = Nid::generate(name.as_bytes());
+        }
+        let at = object.append(exports, bytes_of(&export), 4);
+        let field = |field: &u32| at + field_offset(&export, field);
+        object.point_to(exports, field(&export.nid_table.0), tables, nid_table);
+        object.point_to(exports, field(&export.entry_table.0), tables, entry_table);
+        if let Some(libname) = libname {
+            object.point_to(exports, field(&export.libname.0), tables, libname);
+        }
+    }
+    exports
+}
+
+/// Offset of a field from the start of the structure containing it.
+pub(crate) fn field_offset<T, F>(base: &T, field: &F) -> u32 {
+    (field as *const F as usize - base as *const T as usize) as u32
+}
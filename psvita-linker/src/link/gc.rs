@@ -0,0 +1,157 @@
+//! Removal of unreferenced input sections for `--gc-sections`.
+
+use super::{map, Linker, ObjectId, SectionId, SymbolSection, EH_FRAME_SECTION, ENTRY_SYMBOL};
+use log::debug;
+use object::elf;
+use psvita_sce_types::nid::noname;
+use std::collections::{HashMap, HashSet};
+
+/// Prefixes of sections which are kept even if nothing references them.
+const KEEP_SECTIONS: &[&str] = &[
+    ".sceModuleInfo",
+    ".init_array",
+    ".fini_array",
+    ".preinit_array",
+    ".ctors",
+    ".dtors",
+];
+
+/// `SHF_GNU_RETAIN`, which is missing from the `object` crate.
+const SHF_GNU_RETAIN: u32 = 0x20_0000;
+
+impl Linker<'_> {
+    /// Mark sections reachable from the roots and discard the rest in inputs
+    /// linked with `--gc-sections`.
+    pub fn collect_garbage(&mut self) {
+        if !self.objects.iter().any(|o| o.gc_sections) {
+            return;
+        }
+
+        let mut live = HashSet::new();
+        let mut worklist = self.gc_roots();
+
+        // `SHF_LINK_ORDER` sections like `.ARM.exidx` live as long as the section they describe
+        let mut dependents: HashMap<SectionId, Vec<SectionId>> = HashMap::new();
+        for (object_index, object) in self.objects.iter().enumerate() {
+            for (index, section) in object.sections.iter().enumerate() {
+                if let Some(section) = section {
+                    if section.flags & elf::SHF_LINK_ORDER != 0 {
+                        let owner = SectionId {
+                            object: ObjectId(object_index),
+                            index: section.link as usize,
+                        };
+                        dependents.entry(owner).or_default().push(SectionId {
+                            object: ObjectId(object_index),
+                            index,
+                        });
+                    }
+                }
+            }
+        }
+
+        while !worklist.is_empty() {
+            while let Some(id) = worklist.pop() {
+                if !live.insert(id) {
+                    continue;
+                }
+                let section = match self.objects[id.object.0].section(id.index) {
+                    Some(section) => section,
+                    None => continue,
+                };
+                for rel in &section.relocations {
+                    if let Some(target) = self.symbol_section(id.object, rel.symbol) {
+                        worklist.push(target);
+                    }
+                }
+                worklist.extend(dependents.get(&id).into_iter().flatten());
+            }
+            // `.eh_frame` does not keep code alive, but the frames of live code keep what
+            // they refer to
+            worklist.extend(
+                self.eh_frame_references(&live)
+                    .into_iter()
+                    .filter(|id| !live.contains(id)),
+            );
+        }
+
+        for (object_index, object) in self.objects.iter_mut().enumerate() {
+            if !object.gc_sections {
+                continue;
+            }
+            for (index, section) in object.sections.iter_mut().enumerate() {
+                let id = SectionId {
+                    object: ObjectId(object_index),
+                    index,
+                };
+                let collect = section
+                    .as_ref()
+                    .is_some_and(|s| s.is_alloc() && s.name != EH_FRAME_SECTION);
+                if collect && !live.contains(&id) {
+                    debug!(
+                        "{}: discarding unused section `{}`",
+                        object.name,
+                        section.as_ref().unwrap().name
+                    );
+                    map::discard(&mut self.discarded, &object.name, section, "unused");
+                }
+            }
+        }
+    }
+
+    /// Sections which are live no matter what references them.
+    fn gc_roots(&self) -> Vec<SectionId> {
+        let mut roots = Vec::new();
+
+        let mut root_symbols = vec![ENTRY_SYMBOL];
+        root_symbols.extend(noname::NID_TABLE.iter().map(|(name, _)| *name));
+        root_symbols.extend(
+            self.symbols
+                .iter()
+                .map(|(name, _)| name)
+                .filter(|name| self.is_exported(name)),
+        );
+        for name in root_symbols {
+            if let Some(def) = self.symbols.definition(name) {
+                roots.extend(self.symbol_section(def.object, def.index));
+            }
+        }
+
+        for (object_index, object) in self.objects.iter().enumerate() {
+            for (index, section) in object.sections.iter().enumerate() {
+                let section = match section {
+                    Some(section) => section,
+                    None => continue,
+                };
+                // references from debug sections do not keep anything alive
+                if !section.is_alloc() {
+                    continue;
+                }
+                let keep = !object.gc_sections
+                    || section.sh_type == elf::SHT_NOTE
+                    || section.sh_type == elf::SHT_INIT_ARRAY
+                    || section.sh_type == elf::SHT_FINI_ARRAY
+                    || section.sh_type == elf::SHT_PREINIT_ARRAY
+                    || section.flags & SHF_GNU_RETAIN != 0
+                    || KEEP_SECTIONS
+                        .iter()
+                        .any(|prefix| section.name.starts_with(prefix));
+                if keep {
+                    roots.push(SectionId {
+                        object: ObjectId(object_index),
+                        index,
+                    });
+                }
+            }
+        }
+        roots
+    }
+
+    /// Input section which defines the symbol a reference binds to.
+    pub(crate) fn symbol_section(&self, object: ObjectId, index: usize) -> Option<SectionId> {
+        let (object, index) = self.resolve_symbol(object, index)?;
+        match self.objects[object.0].symbols[index].section {
+            SymbolSection::Section(index) => Some(SectionId { object, index }),
+            _ => None,
+        }
+    }
+}
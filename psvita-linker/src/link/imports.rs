@@ -0,0 +1,477 @@
+//! `SceModuleImport` tables, generated from the stubs of vitasdk `*_stub.a` libraries.
+//!
+//! Each stub is a 16 byte entry in a `.vitalink.fstubs.<library>` or
+//! `.vitalink.vstubs.<library>` section, holding flags, the library NID, the NID of the
+//! function or variable and padding. The symbol naming the import points at its entry.
+
+use super::{
+    exports::field_offset, map, relocation, synthetic::SyntheticObject, InputSymbol, Layout,
+    LinkError, Linker, ObjectId, SymbolSection,
+};
+use bytemuck::{bytes_of, Zeroable};
+use log::{debug, warn};
+use object::elf;
+use psvita_sce_types::{
+    module_imports::{FunctionStubPlaceholder, SceModuleImport, SceVariableRelocations},
+    nid::Nid,
+    relocation::SceRelocationLong,
+    Ptr,
+};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    convert::TryInto,
+    mem,
+};
+
+/// Section holding the `SceModuleImport` structures, which the module info points at.
+pub const IMPORTS_SECTION: &str = ".sceLib.stub";
+/// Section holding the function stubs which the loader patches.
+pub const FUNCTION_STUBS_SECTION: &str = ".sceStub.text";
+/// Prefix of vitasdk function stub sections.
+pub const FUNCTION_STUBS_PREFIX: &str = ".vitalink.fstubs";
+/// Prefix of vitasdk variable stub sections.
+pub const VARIABLE_STUBS_PREFIX: &str = ".vitalink.vstubs";
+
+/// Size of a single stub entry.
+const STUB_SIZE: usize = 16;
+
+/// Library imported by the output.
+#[derive(Debug)]
+pub struct ImportLibrary {
+    /// Taken from the stub section name, `None` for stubs which do not carry it.
+    pub name: Option<String>,
+    pub nid: Nid,
+    /// [`SceLibraryAttribute`](psvita_sce_types::SceLibraryAttribute) bits of the stubs.
+    pub flags: u16,
+    /// Sorted by NID.
+    pub functions: Vec<Import>,
+    /// Sorted by NID.
+    pub variables: Vec<Import>,
+}
+
+#[derive(Debug)]
+pub struct Import {
+    pub nid: Nid,
+    /// Global symbol defined at the stub entry.
+    pub symbol: String,
+    /// References which the loader patches, for variables only.
+    pub references: Vec<VariableReference>,
+    /// Offset of the relocations of the references in the variable relocations section.
+    pub relocations: Option<u32>,
+}
+
+/// Reference to an imported variable, taken over from the relocations of an input section.
+#[derive(Debug)]
+pub struct VariableReference {
+    pub object: ObjectId,
+    /// Local symbol which the linker defines at the referencing word.
+    pub place: usize,
+    pub r_type: u32,
+    pub addend: u32,
+}
+
+impl Linker<'_> {
+    /// Libraries imported through the stubs of the linked objects, sorted by name.
+    ///
+    /// Stubs of `--as-needed` objects are imported only when a relocation refers to them,
+    /// so that a library none of which is referenced does not get loaded. Libraries of
+    /// modules linked without `--as-needed` are imported even without any stubs.
+    pub fn import_libraries(&self) -> Vec<ImportLibrary> {
+        let mut libraries: BTreeMap<(Option<String>, Nid), ImportLibrary> = BTreeMap::new();
+        let referenced = if self.objects.iter().any(|o| o.as_needed) {
+            self.referenced_globals()
+        } else {
+            HashSet::new()
+        };
+
+        for (object_index, object) in self.objects.iter().enumerate() {
+            for symbol in &object.symbols {
+                let index = match symbol.section {
+                    SymbolSection::Section(index) if !symbol.is_local() => index,
+                    _ => continue,
+                };
+                let section = match object.section(index) {
+                    Some(section) => section,
+                    None => continue,
+                };
+                let (suffix, function) =
+                    if let Some(suffix) = section.name.strip_prefix(FUNCTION_STUBS_PREFIX) {
+                        (suffix, true)
+                    } else if let Some(suffix) = section.name.strip_prefix(VARIABLE_STUBS_PREFIX) {
+                        (suffix, false)
+                    } else {
+                        continue;
+                    };
+                // only the definition which won resolution is imported
+                if self.symbols.definition(&symbol.name).map(|d| d.object.0) != Some(object_index) {
+                    continue;
+                }
+                if object.as_needed && !referenced.contains(symbol.name.as_str()) {
+                    debug!("{}: `{}` is not needed", object.name, symbol.name);
+                    continue;
+                }
+
+                let offset = symbol.offset() as usize;
+                let entry = match section.data.get(offset..offset + STUB_SIZE) {
+                    Some(entry) => entry,
+                    None => continue,
+                };
+                let word =
+                    |i: usize| u32::from_le_bytes(entry[i * 4..i * 4 + 4].try_into().unwrap());
+                let name = suffix.strip_prefix('.').map(str::to_owned);
+                let library_nid = Nid(word(1));
+                let library = libraries
+                    .entry((name.clone(), library_nid))
+                    .or_insert_with(|| ImportLibrary {
+                        name,
+                        nid: library_nid,
+                        flags: word(0) as u16,
+                        functions: Vec::new(),
+                        variables: Vec::new(),
+                    });
+                let import = Import {
+                    nid: Nid(word(2)),
+                    symbol: symbol.name.clone(),
+                    references: Vec::new(),
+                    relocations: None,
+                };
+                if function {
+                    library.functions.push(import);
+                } else {
+                    library.variables.push(import);
+                }
+            }
+        }
+
+        for (name, nid) in &self.needed_libraries {
+            libraries
+                .entry((Some(name.clone()), *nid))
+                .or_insert_with(|| ImportLibrary {
+                    name: Some(name.clone()),
+                    nid: *nid,
+                    flags: 0,
+                    functions: Vec::new(),
+                    variables: Vec::new(),
+                });
+        }
+
+        let mut libraries: Vec<_> = libraries.into_values().collect();
+        for library in &mut libraries {
+            library.functions.sort_by_key(|i| i.nid);
+            library.variables.sort_by_key(|i| i.nid);
+            debug!(
+                "importing {} functions and {} variables from `{}` ({:?})",
+                library.functions.len(),
+                library.variables.len(),
+                library.name.as_deref().unwrap_or("?"),
+                library.nid,
+            );
+        }
+        libraries
+    }
+
+    /// Names of global symbols which relocations of the linked sections refer to.
+    fn referenced_globals(&self) -> HashSet<&str> {
+        let mut referenced = HashSet::new();
+        for object in &self.objects {
+            for section in object.sections.iter().flatten() {
+                for rel in &section.relocations {
+                    let symbol = &object.symbols[rel.symbol];
+                    if !symbol.is_local() {
+                        referenced.insert(symbol.name.as_str());
+                    }
+                }
+            }
+        }
+        referenced
+    }
+
+    /// Replace vitasdk stub entries of imported functions with stubs for the loader to patch.
+    ///
+    /// References to the imported functions are redirected to the new stubs, while the
+    /// stub entries are discarded, as the import tables carry everything they held.
+    pub(crate) fn add_function_stubs(
+        &mut self,
+        object: &mut SyntheticObject,
+        libraries: &[ImportLibrary],
+    ) {
+        let functions: Vec<_> = libraries.iter().flat_map(|l| &l.functions).collect();
+        if !functions.is_empty() {
+            let text = object.add_section(FUNCTION_STUBS_SECTION, elf::SHF_EXECINSTR, 16);
+            let stub = FunctionStubPlaceholder::new();
+            for import in functions {
+                let offset = object.append(text, bytes_of(&stub), 16);
+                let size = mem::size_of::<FunctionStubPlaceholder>() as u32;
+                object.define_global(&import.symbol, text, offset, size, elf::STT_FUNC);
+                self.symbols.remove_definition(&import.symbol);
+            }
+        }
+
+        for file in &mut self.objects {
+            for section in &mut file.sections {
+                if section
+                    .as_ref()
+                    .is_some_and(|s| s.name.starts_with(FUNCTION_STUBS_PREFIX))
+                {
+                    map::discard(
+                        &mut self.discarded,
+                        &file.name,
+                        section,
+                        "replaced by generated function stubs",
+                    );
+                }
+            }
+        }
+    }
+
+    /// Move relocations against imported variables out of the allocated input sections and
+    /// into the imports, for the loader to apply once it knows where the variables are.
+    ///
+    /// Relocations of non-allocated sections, like debug info, are resolved statically.
+    ///
+    /// The variable stub entries are discarded afterwards, as nothing refers to them anymore.
+    pub(crate) fn take_variable_references(
+        &mut self,
+        libraries: &mut [ImportLibrary],
+    ) -> Result<(), LinkError> {
+        let mut variables: HashMap<String, &mut Import> = libraries
+            .iter_mut()
+            .flat_map(|l| &mut l.variables)
+            .map(|import| (import.symbol.clone(), import))
+            .collect();
+
+        for object_index in 0..self.objects.len() {
+            let id = ObjectId(object_index);
+            for index in 0..self.objects[object_index].sections.len() {
+                let file = &mut self.objects[object_index];
+                let section = match &mut file.sections[index] {
+                    Some(section) if section.is_alloc() => section,
+                    _ => continue,
+                };
+                let symbols = &file.symbols;
+                let (taken, kept): (Vec<_>, Vec<_>) = section.relocations.iter().partition(|rel| {
+                    let symbol = &symbols[rel.symbol];
+                    !symbol.is_local() && variables.contains_key(&symbol.name)
+                });
+                if taken.is_empty() {
+                    continue;
+                }
+                section.relocations = kept;
+
+                for rel in taken {
+                    let r_type = match rel.r_type {
+                        elf::R_ARM_ABS32 | elf::R_ARM_TARGET1 => elf::R_ARM_ABS32,
+                        elf::R_ARM_MOVW_ABS_NC
+                        | elf::R_ARM_MOVT_ABS
+                        | elf::R_ARM_THM_MOVW_ABS_NC
+                        | elf::R_ARM_THM_MOVT_ABS => rel.r_type,
+                        r_type => {
+                            return Err(LinkError::UnsupportedRelocation {
+                                object: file.name.clone(),
+                                section: section.name.clone(),
+                                r_type,
+                            })
+                        }
+                    };
+                    let offset = rel.offset as usize;
+                    let word = section
+                        .data
+                        .get(offset..offset + 4)
+                        .map_or(0, |w| u32::from_le_bytes(w.try_into().unwrap()));
+                    let addend = rel.addend.map_or_else(
+                        || relocation::implicit_addend(rel.r_type, word),
+                        |a| a as u32,
+                    );
+
+                    let import = variables.get_mut(&file.symbols[rel.symbol].name).unwrap();
+                    file.symbols.push(InputSymbol {
+                        name: format!("{}$ref{}", import.symbol, import.references.len()),
+                        value: rel.offset,
+                        size: 0,
+                        kind: elf::STT_NOTYPE,
+                        binding: elf::STB_LOCAL,
+                        visibility: elf::STV_DEFAULT,
+                        section: SymbolSection::Section(index),
+                    });
+                    import.references.push(VariableReference {
+                        object: id,
+                        place: file.symbols.len() - 1,
+                        r_type,
+                        addend,
+                    });
+                }
+            }
+        }
+
+        for file in &mut self.objects {
+            for section in &mut file.sections {
+                if section
+                    .as_ref()
+                    .is_some_and(|s| s.name.starts_with(VARIABLE_STUBS_PREFIX))
+                {
+                    map::discard(
+                        &mut self.discarded,
+                        &file.name,
+                        section,
+                        "replaced by variable relocation lists",
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Fill the relocations of variable references in the laid out image.
+    ///
+    /// They are always written in the long form, as their size has to be known before the
+    /// layout is.
+    pub(crate) fn write_variable_relocations(&self, layout: &mut Layout) {
+        let id = match self.variable_relocations {
+            Some(id) => id,
+            None => return,
+        };
+        let (output, base) = layout.placement(id).unwrap();
+
+        for variable in self.imports.iter().flat_map(|l| &l.variables) {
+            let mut at = match variable.relocations {
+                Some(offset) => (base + offset) as usize,
+                None => continue,
+            };
+            for reference in &variable.references {
+                let place = self
+                    .symbol_value(layout, reference.object, reference.place)
+                    .and_then(|place| layout.segment_offset(place.address));
+                let relocation = place.and_then(|(datseg, offset)| {
+                    let code = reference.r_type as u8;
+                    SceRelocationLong::new(0, code, datseg as u8, offset, reference.addend)
+                });
+                match relocation {
+                    Some(relocation) => layout.sections[output].data
+                        [at..at + mem::size_of::<SceRelocationLong>()]
+                        .copy_from_slice(bytes_of(&relocation)),
+                    None => warn!(
+                        "reference to `{}` is outside of every segment",
+                        variable.symbol
+                    ),
+                }
+                at += mem::size_of::<SceRelocationLong>();
+            }
+        }
+    }
+}
+
+/// Write the import structures and their tables.
+///
+/// Returns the index of the section holding the relocations of variable references, which
+/// [`Linker::write_variable_relocations`] fills once the layout is known.
+pub(crate) fn add_import_tables(
+    object: &mut SyntheticObject,
+    libraries: &mut [ImportLibrary],
+) -> Option<usize> {
+    if libraries.is_empty() {
+        return None;
+    }
+    let imports = object.add_section(IMPORTS_SECTION, 0, 4);
+    let names = object.add_section(".sceImport.rodata", 0, 4);
+    let function_nids = object.add_section(".sceFNID.rodata", 0, 4);
+    let function_entries = object.add_section(".sceFStub.rodata", 0, 4);
+    let variable_nids = object.add_section(".sceVNID.rodata", 0, 4);
+    let variable_entries = object.add_section(".sceVStub.rodata", 0, 4);
+    let variable_relocations = object.add_section(".sceVRel.rodata", 0, 4);
+
+    for library in libraries.iter_mut() {
+        let mut import = SceModuleImport::zeroed();
+        import.common.size = mem::size_of::<SceModuleImport>() as u16;
+        import.common.version = 1;
+        import.common.flags = library.flags;
+        import.common.num_syms_funcs = library.functions.len() as u16;
+        import.common.num_syms_vars = library.variables.len() as u16;
+        import.library_nid = library.nid.0;
+        let at = object.append(imports, bytes_of(&import), 4);
+        let field = |field: &u32| at + field_offset(&import, field);
+
+        if let Some(name) = &library.name {
+            let mut data = name.as_bytes().to_vec();
+            data.push(0);
+            let name = object.append(names, &data, 4);
+            object.point_to(imports, field(&import.library_name.0), names, name);
+        }
+        if !library.functions.is_empty() {
+            let table = nid_table(&library.functions);
+            let nid_table = object.append(function_nids, &table, 4);
+            let entry_table = object.append(function_entries, &vec![0; table.len()], 4);
+            object.point_to(
+                imports,
+                field(&import.func_nid_table.0),
+                function_nids,
+                nid_table,
+            );
+            object.point_to(
+                imports,
+                field(&import.func_entry_table.0),
+                function_entries,
+                entry_table,
+            );
+            for (index, function) in library.functions.iter().enumerate() {
+                let symbol = object.reference(&function.symbol);
+                let offset = entry_table + index as u32 * 4;
+                object.relocate(function_entries, offset, elf::R_ARM_ABS32, symbol);
+            }
+        }
+
+        if !library.variables.is_empty() {
+            let table = nid_table(&library.variables);
+            let nid_table = object.append(variable_nids, &table, 4);
+            let entry_table = object.append(variable_entries, &vec![0; table.len()], 4);
+            object.point_to(
+                imports,
+                field(&import.var_nid_table.0),
+                variable_nids,
+                nid_table,
+            );
+            object.point_to(
+                imports,
+                field(&import.var_entry_table.0),
+                variable_entries,
+                entry_table,
+            );
+            for (index, variable) in library.variables.iter_mut().enumerate() {
+                let list = add_variable_relocations(object, variable_relocations, variable);
+                let offset = entry_table + index as u32 * 4;
+                object.point_to(variable_entries, offset, variable_relocations, list);
+            }
+        }
+    }
+    Some(variable_relocations)
+}
+
+fn nid_table(imports: &[Import]) -> Vec<u8> {
+    imports.iter().flat_map(|i| i.nid.0.to_le_bytes()).collect()
+}
+
+/// Reserve the relocations of a variable and write the list pointing at them, returns the
+/// offset of the list in the section.
+fn add_variable_relocations(
+    object: &mut SyntheticObject,
+    section: usize,
+    variable: &mut Import,
+) -> u32 {
+    let length = variable.references.len() * mem::size_of::<SceRelocationLong>();
+    let relocations = object.append(section, &vec![0; length], 4);
+    variable.relocations = Some(relocations);
+
+    let list = SceVariableRelocations {
+        length: length as u32,
+        relocations: Ptr::new(0),
+    };
+    let at = object.append(section, bytes_of(&list), 4);
+    if length != 0 {
+        object.point_to(
+            section,
+            at + field_offset(&list, &list.relocations.0),
+            section,
+            relocations,
+        );
+    }
+    at
+}
@@ -0,0 +1,297 @@
+//! Placement of input sections into output sections and segments.
+
+use super::{ObjectFile, ObjectId, SectionId};
+use object::elf;
+use std::{collections::HashMap, ops::Range};
+
+/// Default load address of psvita modules.
+pub const BASE_ADDRESS: u32 = 0x8100_0000;
+/// Default alignment of segment start addresses.
+pub const SEGMENT_ALIGN: u32 = 0x1000;
+
+#[derive(Debug)]
+pub struct OutputSection {
+    pub name: String,
+    pub sh_type: u32,
+    pub flags: u32,
+    pub align: u32,
+    pub address: u32,
+    pub size: u32,
+    /// Empty for `SHT_NOBITS` sections.
+    pub data: Vec<u8>,
+    /// Input sections with their offsets inside of this output section.
+    pub inputs: Vec<(SectionId, u32)>,
+}
+
+#[derive(Debug)]
+pub struct Segment {
+    /// `PF_*` flags
+    pub flags: u32,
+    /// Range of indices into [`Layout::sections`].
+    pub sections: Range<usize>,
+    pub address: u32,
+    pub file_size: u32,
+    pub mem_size: u32,
+    pub align: u32,
+}
+
+/// Initial image of the thread-local storage of every thread, made of `.tdata` and `.tbss`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TlsTemplate {
+    pub address: u32,
+    /// Size of the initialized part, which is followed by zeros up to `mem_size`.
+    pub file_size: u32,
+    pub mem_size: u32,
+    pub align: u32,
+}
+
+#[derive(Debug, Default)]
+pub struct Layout {
+    pub sections: Vec<OutputSection>,
+    pub segments: Vec<Segment>,
+    pub tls: Option<TlsTemplate>,
+    /// Output section index and offset within it for each placed input section.
+    placements: HashMap<SectionId, (usize, u32)>,
+}
+
+/// Coarse position of an output section in the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Rank {
+    Text,
+    ReadOnly,
+    RelRo,
+    Tls,
+    /// Takes no address space, as the template only gets copied.
+    TlsBss,
+    Data,
+    Bss,
+    NonAlloc,
+}
+
+impl Rank {
+    fn of(section: &OutputSection) -> Rank {
+        let flags = section.flags;
+        if flags & elf::SHF_ALLOC == 0 {
+            Rank::NonAlloc
+        } else if flags & elf::SHF_WRITE == 0 {
+            if flags & elf::SHF_EXECINSTR != 0 {
+                Rank::Text
+            } else {
+                Rank::ReadOnly
+            }
+        } else if flags & elf::SHF_TLS != 0 {
+            if section.sh_type == elf::SHT_NOBITS {
+                Rank::TlsBss
+            } else {
+                Rank::Tls
+            }
+        } else if section.sh_type == elf::SHT_NOBITS {
+            Rank::Bss
+        } else if section.name == ".data.rel.ro"
+            || section.sh_type == elf::SHT_INIT_ARRAY
+            || section.sh_type == elf::SHT_FINI_ARRAY
+            || section.sh_type == elf::SHT_PREINIT_ARRAY
+        {
+            Rank::RelRo
+        } else {
+            Rank::Data
+        }
+    }
+
+    fn segment_flags(self) -> Option<u32> {
+        match self {
+            Rank::Text | Rank::ReadOnly => Some(elf::PF_R | elf::PF_X),
+            Rank::RelRo | Rank::Tls | Rank::TlsBss | Rank::Data | Rank::Bss => {
+                Some(elf::PF_R | elf::PF_W)
+            }
+            Rank::NonAlloc => None,
+        }
+    }
+}
+
+/// Name of the output section an input section is merged into.
+pub fn output_section_name(name: &str) -> &str {
+    const PREFIXES: &[&str] = &[
+        ".text",
+        ".rodata",
+        ".data.rel.ro",
+        ".data",
+        ".bss",
+        ".tdata",
+        ".tbss",
+        ".init_array",
+        ".fini_array",
+        ".ARM.exidx",
+        ".ARM.extab",
+        ".gcc_except_table",
+    ];
+
+    for prefix in PREFIXES {
+        if let Some(rest) = name.strip_prefix(prefix) {
+            if rest.is_empty() || rest.starts_with('.') {
+                return prefix;
+            }
+        }
+    }
+    name
+}
+
+impl Layout {
+    /// Merge live input sections into output sections and assign addresses to them.
+    pub fn new(objects: &[ObjectFile], segment_align: u32) -> Layout {
+        let mut layout = Layout::default();
+        let mut by_name = HashMap::new();
+
+        for (object_index, object) in objects.iter().enumerate() {
+            for (index, section) in object.sections.iter().enumerate() {
+                let section = match section {
+                    Some(s) => s,
+                    None => continue,
+                };
+                let name = output_section_name(&section.name);
+                let output_index = *by_name.entry(name.to_owned()).or_insert_with(|| {
+                    layout.sections.push(OutputSection {
+                        name: name.to_owned(),
+                        sh_type: section.sh_type,
+                        flags: 0,
+                        align: 1,
+                        address: 0,
+                        size: 0,
+                        data: Vec::new(),
+                        inputs: Vec::new(),
+                    });
+                    layout.sections.len() - 1
+                });
+
+                let output = &mut layout.sections[output_index];
+                if output.sh_type == elf::SHT_NOBITS {
+                    output.sh_type = section.sh_type;
+                }
+                output.flags |= section.flags
+                    & (elf::SHF_ALLOC | elf::SHF_WRITE | elf::SHF_EXECINSTR | elf::SHF_TLS);
+                output.align = output.align.max(section.align);
+                let id = SectionId {
+                    object: ObjectId(object_index),
+                    index,
+                };
+                output.inputs.push((id, 0));
+            }
+        }
+
+        // stable sort keeps the command line order within the same rank
+        layout.sections.sort_by_key(Rank::of);
+        layout.assign_addresses(objects, segment_align);
+        layout
+    }
+
+    fn assign_addresses(&mut self, objects: &[ObjectFile], segment_align: u32) {
+        let mut address = BASE_ADDRESS;
+        let mut current_flags = None;
+
+        for output_index in 0..self.sections.len() {
+            let rank = Rank::of(&self.sections[output_index]);
+            let segment_flags = rank.segment_flags();
+            if let Some(flags) = segment_flags.filter(|&f| Some(f) != current_flags) {
+                address = align_up(address, segment_align);
+                current_flags = Some(flags);
+                self.segments.push(Segment {
+                    flags,
+                    sections: output_index..output_index,
+                    address,
+                    file_size: 0,
+                    mem_size: 0,
+                    align: 1,
+                });
+            }
+
+            let output = &mut self.sections[output_index];
+            let mut offset = 0;
+            for (id, input_offset) in &mut output.inputs {
+                let section = objects[id.object.0].section(id.index).unwrap();
+                offset = align_up(offset, section.align);
+                *input_offset = offset;
+                self.placements.insert(*id, (output_index, offset));
+                offset += section.size;
+            }
+            output.size = offset;
+
+            if output.sh_type != elf::SHT_NOBITS {
+                output.data = vec![0; offset as usize];
+                for (id, input_offset) in &output.inputs {
+                    let section = objects[id.object.0].section(id.index).unwrap();
+                    let start = *input_offset as usize;
+                    output.data[start..start + section.data.len()].copy_from_slice(&section.data);
+                }
+            }
+
+            if segment_flags.is_none() {
+                continue;
+            }
+            address = align_up(address, output.align);
+            output.address = address;
+            if rank == Rank::Tls || rank == Rank::TlsBss {
+                let tls = self.tls.get_or_insert(TlsTemplate {
+                    address,
+                    file_size: 0,
+                    mem_size: 0,
+                    align: 1,
+                });
+                tls.mem_size = address + output.size - tls.address;
+                if rank == Rank::Tls {
+                    tls.file_size = tls.mem_size;
+                }
+                tls.align = tls.align.max(output.align);
+            }
+            if rank != Rank::TlsBss {
+                address += output.size;
+            }
+
+            let segment = self.segments.last_mut().unwrap();
+            segment.sections.end = output_index + 1;
+            segment.align = segment.align.max(output.align);
+            segment.mem_size = address - segment.address;
+            if output.sh_type != elf::SHT_NOBITS {
+                segment.file_size = segment.mem_size;
+            }
+        }
+    }
+
+    /// Output section index and offset of a placed input section.
+    pub fn placement(&self, id: SectionId) -> Option<(usize, u32)> {
+        self.placements.get(&id).copied()
+    }
+
+    /// Address of a placed input section.
+    pub fn section_address(&self, id: SectionId) -> Option<u32> {
+        self.placement(id)
+            .map(|(output, offset)| self.sections[output].address + offset)
+    }
+
+    /// Index of the segment containing `address` and the offset of the address into it.
+    ///
+    /// The end of a segment only counts as a part of it if no other segment starts there,
+    /// since segments are mapped independently of each other.
+    pub fn segment_offset(&self, address: u32) -> Option<(usize, u32)> {
+        let index = self
+            .segments
+            .iter()
+            .position(|s| (s.address..s.address + s.mem_size).contains(&address))
+            .or_else(|| {
+                self.segments
+                    .iter()
+                    .position(|s| s.address + s.mem_size == address)
+            })?;
+        Some((index, address - self.segments[index].address))
+    }
+
+    /// Index of the output section with the given name.
+    pub fn find_section(&self, name: &str) -> Option<usize> {
+        self.sections.iter().position(|s| s.name == name)
+    }
+}
+
+/// Round `value` up to a power of two `align`.
+pub fn align_up(value: u32, align: u32) -> u32 {
+    let mask = align.max(1) - 1;
+    (value + mask) & !mask
+}
@@ -0,0 +1,196 @@
+//! Link map, a readable account of what went into the output and why.
+
+use super::{InputSection, Layout, LinkError, Linker, ObjectId, SectionId, SymbolSection};
+use object::elf;
+use std::{
+    collections::HashMap,
+    fmt::{self, Write as _},
+    fs,
+    io::{self, Write as _},
+    path::Path,
+};
+
+/// Archive member loaded into the link.
+#[derive(Debug)]
+pub struct LoadedMember {
+    /// Name of the member as `archive(member)`.
+    pub member: String,
+    /// Referencing object and symbol, or the option which loaded the member.
+    pub reason: String,
+}
+
+/// Input section left out of the output.
+#[derive(Debug)]
+pub struct DiscardedSection {
+    pub object: String,
+    pub section: String,
+    pub reason: &'static str,
+}
+
+/// Take the section out of the link, recording why.
+pub(crate) fn discard(
+    discarded: &mut Vec<DiscardedSection>,
+    object: &str,
+    section: &mut Option<InputSection>,
+    reason: &'static str,
+) {
+    if let Some(section) = section.take() {
+        discarded.push(DiscardedSection {
+            object: object.to_owned(),
+            section: section.name,
+            reason,
+        });
+    }
+}
+
+/// Write the link map into `path`, or into the standard output for `-`.
+pub fn write_map(linker: &Linker<'_>, layout: &Layout, path: &Path) -> Result<(), LinkError> {
+    let mut map = String::new();
+    linker.format_map(layout, &mut map).unwrap();
+
+    let result = if path == Path::new("-") {
+        io::stdout().write_all(map.as_bytes())
+    } else {
+        fs::write(path, map)
+    };
+    result.map_err(|source| LinkError::Write {
+        path: path.to_owned(),
+        source,
+    })
+}
+
+impl Linker<'_> {
+    fn format_map(&self, layout: &Layout, out: &mut String) -> fmt::Result {
+        writeln!(
+            out,
+            "Archive member included to satisfy reference by file (symbol)\n"
+        )?;
+        for member in &self.loaded_members {
+            writeln!(out, "{}\n{:30}{}", member.member, "", member.reason)?;
+        }
+
+        writeln!(out, "\nDiscarded input sections\n")?;
+        for discarded in &self.discarded {
+            writeln!(
+                out,
+                " {:<30} {} ({})",
+                discarded.section, discarded.object, discarded.reason
+            )?;
+        }
+
+        writeln!(out, "\nMemory map\n")?;
+        let symbols = self.symbols_by_section(layout);
+        for output in &layout.sections {
+            writeln!(
+                out,
+                "{:<16} {:#010x} {:#10x}",
+                output.name, output.address, output.size
+            )?;
+            for &(id, offset) in &output.inputs {
+                let section = self.objects[id.object.0].section(id.index).unwrap();
+                writeln!(
+                    out,
+                    " {:<15} {:#010x} {:#10x} {}",
+                    section.name,
+                    output.address + offset,
+                    section.size,
+                    self.objects[id.object.0].name
+                )?;
+                for (address, name) in symbols.get(&id).into_iter().flatten() {
+                    writeln!(out, "{:16} {:#010x}            {}", "", address, name)?;
+                }
+            }
+        }
+
+        writeln!(out, "\nExports\n")?;
+        for library in &self.exports {
+            writeln!(
+                out,
+                "{} ({:?})",
+                library.name.as_deref().unwrap_or("NONAME"),
+                library.attribute
+            )?;
+            for (kind, exports) in [
+                ("function", &library.functions),
+                ("variable", &library.variables),
+                ("tls", &library.tls),
+            ] {
+                for export in exports {
+                    writeln!(out, " {:<8} {:#010x} {}", kind, export.nid.0, export.symbol)?;
+                }
+            }
+        }
+
+        writeln!(out, "\nImports\n")?;
+        for library in &self.imports {
+            writeln!(
+                out,
+                "{} ({:#010x})",
+                library.name.as_deref().unwrap_or("<unnamed>"),
+                library.nid.0
+            )?;
+            for import in &library.functions {
+                let stub = self
+                    .global_value(layout, &import.symbol)
+                    .map_or(0, |v| v.address);
+                writeln!(
+                    out,
+                    " function {:#010x} {} stub at {:#010x}",
+                    import.nid.0, import.symbol, stub
+                )?;
+            }
+            for import in &library.variables {
+                writeln!(
+                    out,
+                    " variable {:#010x} {} referenced {} times",
+                    import.nid.0,
+                    import.symbol,
+                    import.references.len()
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Named symbols of every placed input section, sorted by address.
+    fn symbols_by_section(&self, layout: &Layout) -> HashMap<SectionId, Vec<(u32, &str)>> {
+        let mut symbols: HashMap<_, Vec<_>> = HashMap::new();
+        for (object_index, object) in self.objects.iter().enumerate() {
+            let id = ObjectId(object_index);
+            for (index, symbol) in object.symbols.iter().enumerate() {
+                let section = match symbol.section {
+                    SymbolSection::Section(section) => section,
+                    _ => continue,
+                };
+                if symbol.name.is_empty()
+                    || symbol.kind == elf::STT_SECTION
+                    || symbol.kind == elf::STT_FILE
+                {
+                    continue;
+                }
+                // only the definition which won
+                if !symbol.is_local()
+                    && self
+                        .symbols
+                        .definition(&symbol.name)
+                        .is_none_or(|def| (def.object, def.index) != (id, index))
+                {
+                    continue;
+                }
+                if let Some(value) = self.symbol_value(layout, id, index) {
+                    symbols
+                        .entry(SectionId {
+                            object: id,
+                            index: section,
+                        })
+                        .or_default()
+                        .push((value.address, symbol.name.as_str()));
+                }
+            }
+        }
+        for list in symbols.values_mut() {
+            list.sort_unstable();
+        }
+        symbols
+    }
+}
@@ -0,0 +1,570 @@
+//! Linking of relocatable psvita ELF objects into an output image.
+
+mod archive;
+mod dynamic;
+mod eh_frame;
+mod error;
+mod exidx;
+mod exports;
+mod gc;
+mod imports;
+mod layout;
+mod map;
+mod module_info;
+mod object_file;
+mod options;
+mod relocation;
+mod search;
+mod symbols;
+mod synthetic;
+mod tls;
+mod veneer;
+mod writer;
+
+pub use archive::Archive;
+pub use eh_frame::{EH_FRAME_HDR_SECTION, EH_FRAME_HDR_SYMBOL, EH_FRAME_SECTION};
+pub use error::{demangle, DuplicateDefinition, LinkError, SymbolLocation, UndefinedReference};
+pub use exidx::{EXIDX_CANTUNWIND, EXIDX_SECTION};
+pub use exports::{Export, ExportLibrary, EXPORTS_SECTION, EXPORT_TABLES_SECTION};
+pub use imports::{
+    Import, ImportLibrary, VariableReference, FUNCTION_STUBS_PREFIX, FUNCTION_STUBS_SECTION,
+    IMPORTS_SECTION, VARIABLE_STUBS_PREFIX,
+};
+pub use layout::{align_up, output_section_name, Layout, OutputSection, Segment, TlsTemplate};
+pub use map::{DiscardedSection, LoadedMember};
+pub use module_info::MODULE_INFO_SECTION;
+pub use object_file::{InputSection, InputSymbol, ObjectFile, Relocation, SymbolSection};
+pub use options::LinkOptions;
+pub use relocation::{sce_relocations, DynamicRelocation};
+pub use search::find_library;
+pub use symbols::{Definition, GlobalSymbol, Strength, SymbolTable};
+pub use synthetic::SyntheticObject;
+pub use tls::{TCB_SIZE, TLS_MODULE_INDEX};
+pub use veneer::{Veneer, VeneerKey};
+pub use writer::{debug_file_path, debug_link_crc};
+
+use crate::input::{Input, InputFile, InputNode, OutputOptions};
+use log::{debug, warn};
+use object::elf;
+use psvita_sce_types::{elf::ENTRY_SEGMENT_SHIFT, nid::Nid};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fs,
+};
+
+/// Symbol where executables start running.
+pub const ENTRY_SYMBOL: &str = "_start";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ObjectId(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SectionId {
+    pub object: ObjectId,
+    pub index: usize,
+}
+
+/// Final value of a symbol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SymbolValue {
+    pub address: u32,
+    pub thumb: bool,
+    /// Not affected by the module base, like `SHN_ABS` or undefined weak symbols.
+    pub absolute: bool,
+}
+
+#[derive(Debug)]
+pub struct Linker<'a> {
+    pub input: &'a Input,
+    pub options: LinkOptions,
+    pub objects: Vec<ObjectFile>,
+    pub symbols: SymbolTable,
+    comdat_signatures: HashSet<String>,
+    pub veneers: Vec<Veneer>,
+    veneer_index: HashMap<VeneerKey, usize>,
+    /// Synthetic object holding the veneer sections.
+    veneer_object: Option<ObjectId>,
+    /// Section of the generated module info.
+    module_info: Option<SectionId>,
+    /// Section of the relocations of references to imported variables.
+    variable_relocations: Option<SectionId>,
+    /// Space for the entries added to `.ARM.exidx` when it gets sorted.
+    exidx_reserve: Option<SectionId>,
+    /// Section of the generated `.eh_frame_hdr`.
+    eh_frame_hdr: Option<SectionId>,
+    /// Archive members in the order they were loaded, for the link map.
+    pub loaded_members: Vec<LoadedMember>,
+    /// Input sections left out of the output, for the link map.
+    pub discarded: Vec<DiscardedSection>,
+    pub exports: Vec<ExportLibrary>,
+    pub imports: Vec<ImportLibrary>,
+    /// Libraries of modules linked without `--as-needed`, imported even if nothing is.
+    needed_libraries: Vec<(String, Nid)>,
+}
+
+/// Kind of an input file, detected from its magic bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    Elf,
+    Archive,
+    Unknown,
+}
+
+impl FileKind {
+    pub fn detect(data: &[u8]) -> FileKind {
+        if data.starts_with(&elf::ELFMAG) {
+            FileKind::Elf
+        } else if data.starts_with(b"!<arch>\n") {
+            FileKind::Archive
+        } else {
+            FileKind::Unknown
+        }
+    }
+}
+
+/// Link everything described by the `input` into its output file.
+pub fn link(input: &Input) -> Result<(), LinkError> {
+    let mut linker = Linker::new(input);
+    for node in &input.inputs {
+        linker.load_node(node)?;
+    }
+    linker.finish()
+}
+
+impl<'a> Linker<'a> {
+    pub fn new(input: &'a Input) -> Self {
+        Linker {
+            input,
+            options: LinkOptions::from_z_keywords(&input.z_keywords),
+            objects: Vec::new(),
+            symbols: SymbolTable::new(),
+            comdat_signatures: HashSet::new(),
+            veneers: Vec::new(),
+            veneer_index: HashMap::new(),
+            veneer_object: None,
+            module_info: None,
+            variable_relocations: None,
+            exidx_reserve: None,
+            eh_frame_hdr: None,
+            loaded_members: Vec::new(),
+            discarded: Vec::new(),
+            exports: Vec::new(),
+            imports: Vec::new(),
+            needed_libraries: Vec::new(),
+        }
+    }
+
+    /// Load a file, a library found in the library paths, or a group.
+    ///
+    /// Returns the archive which was scanned, if it may be scanned again.
+    pub fn load_node(&mut self, node: &InputNode) -> Result<Option<Archive>, LinkError> {
+        match node {
+            InputNode::File(file) => self.load_file(file),
+            InputNode::Library(library) => self.load_file(&InputFile {
+                path: find_library(library, &self.input.library_paths)?,
+                gc_sections: library.gc_sections,
+                whole_archive: library.whole_archive,
+                as_needed: library.as_needed,
+            }),
+            InputNode::Group(nodes) => {
+                self.load_group(nodes)?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Load the files of a group, then scan its archives again until none of them loads
+    /// another member, so that archives may depend on each other in cycles.
+    pub fn load_group(&mut self, nodes: &[InputNode]) -> Result<(), LinkError> {
+        let mut archives = Vec::new();
+        for node in nodes {
+            archives.extend(self.load_node(node)?);
+        }
+        loop {
+            let loaded = self.objects.len();
+            for archive in &mut archives {
+                self.load_archive(archive)?;
+            }
+            if self.objects.len() == loaded {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Load an object or the needed members of an archive.
+    ///
+    /// Returns the archive unless it was loaded whole, for groups to scan it again.
+    pub fn load_file(&mut self, file: &InputFile) -> Result<Option<Archive>, LinkError> {
+        let data = fs::read(&file.path).map_err(|source| LinkError::Read {
+            path: file.path.clone(),
+            source,
+        })?;
+        let name = file.path.display().to_string();
+
+        match FileKind::detect(&data) {
+            FileKind::Elf if dynamic::is_module(&data) => {
+                self.load_module(name, &data, file.as_needed)?;
+                Ok(None)
+            }
+            FileKind::Elf => {
+                let object = ObjectFile::parse(name, &data, file.gc_sections, file.as_needed)?;
+                self.add_object(object)?;
+                Ok(None)
+            }
+            FileKind::Archive => {
+                let mut archive = Archive::parse(name, &data, file.gc_sections, file.as_needed)?;
+                if file.whole_archive {
+                    self.load_whole_archive(archive)?;
+                    Ok(None)
+                } else {
+                    self.load_archive(&mut archive)?;
+                    Ok(Some(archive))
+                }
+            }
+            FileKind::Unknown if data.starts_with(b"SCE\0") => Err(LinkError::UnsupportedInput {
+                name,
+                reason: "encrypted module, link against its `.vso` instead",
+            }),
+            FileKind::Unknown => Err(LinkError::UnsupportedInput {
+                name,
+                reason: "unknown file format",
+            }),
+        }
+    }
+
+    /// Load archive members which define symbols that are still undefined, until there are none.
+    ///
+    /// Symbols which are only referenced weakly do not cause a member to be loaded.
+    pub fn load_archive(&mut self, archive: &mut Archive) -> Result<(), LinkError> {
+        loop {
+            let mut wanted: Vec<_> = self
+                .symbols
+                .undefined()
+                .filter(|(_, sym)| !sym.weak_reference)
+                .filter_map(|(name, _)| Some((archive.member_defining(name)?, name.to_owned())))
+                .collect();
+            if wanted.is_empty() {
+                return Ok(());
+            }
+            wanted.sort();
+            wanted.dedup_by_key(|(member, _)| *member);
+
+            for (member, symbol) in wanted {
+                // an earlier member of this round may have defined it already
+                if self.symbols.definition(&symbol).is_some() {
+                    continue;
+                }
+                if let Some(object) = archive.take_member(member) {
+                    debug!(
+                        "{}: loading member `{}` for `{}`",
+                        archive.name,
+                        archive.member_name(member),
+                        symbol
+                    );
+                    let referenced_by = self
+                        .symbols
+                        .get(&symbol)
+                        .and_then(|s| s.referenced_by)
+                        .map_or_else(String::new, |id| self.objects[id.0].name.clone());
+                    self.loaded_members.push(LoadedMember {
+                        member: object.name.clone(),
+                        reason: format!("{} ({})", referenced_by, symbol),
+                    });
+                    self.add_object(object)?;
+                }
+            }
+        }
+    }
+
+    /// Load every member of the archive, as with `--whole-archive`.
+    ///
+    /// Duplicate definitions brought in by the members are only warned about.
+    pub fn load_whole_archive(&mut self, mut archive: Archive) -> Result<(), LinkError> {
+        for member in 0..archive.member_count() {
+            if let Some(object) = archive.take_member(member) {
+                self.loaded_members.push(LoadedMember {
+                    member: object.name.clone(),
+                    reason: String::from("--whole-archive"),
+                });
+                self.register_object(object, true)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Register a parsed object, dropping sections of already seen COMDAT groups.
+    pub fn add_object(&mut self, object: ObjectFile) -> Result<ObjectId, LinkError> {
+        self.register_object(object, false)
+    }
+
+    fn register_object(
+        &mut self,
+        mut object: ObjectFile,
+        keep_duplicates: bool,
+    ) -> Result<ObjectId, LinkError> {
+        for (signature, members) in &object.comdat_groups {
+            if !self.comdat_signatures.insert(signature.clone()) {
+                debug!(
+                    "{}: discarding duplicate COMDAT group `{}`",
+                    object.name, signature
+                );
+                for &member in members {
+                    if let Some(section) = object.sections.get_mut(member) {
+                        map::discard(
+                            &mut self.discarded,
+                            &object.name,
+                            section,
+                            "duplicate COMDAT group",
+                        );
+                    }
+                }
+            }
+        }
+        for symbol in &mut object.symbols {
+            if let SymbolSection::Section(index) = symbol.section {
+                if !symbol.is_local() && object.sections.get(index).is_none_or(Option::is_none) {
+                    symbol.section = SymbolSection::Undefined;
+                }
+            }
+        }
+
+        let id = ObjectId(self.objects.len());
+        let keep_duplicates = keep_duplicates || self.options.allow_multiple_definitions;
+        self.symbols
+            .add_object(&self.objects, id, &object, keep_duplicates);
+        self.objects.push(object);
+        Ok(id)
+    }
+
+    /// Whether the loader may place the output at any address.
+    pub fn is_relocatable_output(&self) -> bool {
+        !matches!(
+            self.input.output_options,
+            OutputOptions::Executable { pic: false }
+        )
+    }
+
+    /// Whether the global symbol is exported from a shared output.
+    pub fn is_exported(&self, name: &str) -> bool {
+        let visible = self.symbols.definition(name).is_some_and(|def| {
+            let symbol = &self.objects[def.object.0].symbols[def.index];
+            symbol.visibility == elf::STV_DEFAULT || symbol.visibility == elf::STV_PROTECTED
+        });
+        match &self.input.output_options {
+            OutputOptions::Shared {
+                version_script: Some(script),
+            } => visible && script.global.is_match(name.as_bytes()),
+            OutputOptions::Shared {
+                version_script: None,
+            } => visible,
+            OutputOptions::Executable { .. } => false,
+        }
+    }
+
+    /// Symbol which a reference to symbol `index` of the object `object` binds to.
+    ///
+    /// Returns `None` for global symbols which nothing defines.
+    pub fn resolve_symbol(&self, object: ObjectId, index: usize) -> Option<(ObjectId, usize)> {
+        let symbol = &self.objects[object.0].symbols[index];
+        if symbol.is_local() || symbol.kind == elf::STT_SECTION {
+            return Some((object, index));
+        }
+        let def = self.symbols.definition(&symbol.name)?;
+        Some((def.object, def.index))
+    }
+
+    /// Resolve symbol `index` of the object `object` into its final value.
+    ///
+    /// Returns `None` for symbols defined in discarded sections.
+    pub fn symbol_value(
+        &self,
+        layout: &Layout,
+        object: ObjectId,
+        index: usize,
+    ) -> Option<SymbolValue> {
+        let (object, symbol) = match self.resolve_symbol(object, index) {
+            Some((object, index)) => (object, &self.objects[object.0].symbols[index]),
+            None => {
+                return Some(SymbolValue {
+                    address: 0,
+                    thumb: false,
+                    absolute: true,
+                })
+            }
+        };
+
+        match symbol.section {
+            SymbolSection::Undefined | SymbolSection::Common => Some(SymbolValue {
+                address: 0,
+                thumb: false,
+                absolute: true,
+            }),
+            SymbolSection::Absolute => Some(SymbolValue {
+                address: symbol.value,
+                thumb: false,
+                absolute: true,
+            }),
+            SymbolSection::Section(index) => {
+                let base = layout.section_address(SectionId { object, index })?;
+                Some(SymbolValue {
+                    address: base + symbol.offset(),
+                    thumb: symbol.is_thumb(),
+                    absolute: false,
+                })
+            }
+        }
+    }
+
+    /// Fail on symbols with more than one strong definition, listing every duplicate.
+    fn check_duplicates(&self) -> Result<(), LinkError> {
+        match self.symbols.duplicates() {
+            [] => Ok(()),
+            duplicates => Err(LinkError::DuplicateSymbols(duplicates.to_vec())),
+        }
+    }
+
+    /// Fail on references to symbols which nothing defines, listing every referencing section.
+    fn check_undefined(&self) -> Result<(), LinkError> {
+        let undefined: HashSet<_> = self
+            .symbols
+            .undefined()
+            .filter(|(_, sym)| !sym.weak_reference)
+            .map(|(name, _)| name)
+            .collect();
+        if undefined.is_empty() {
+            return Ok(());
+        }
+
+        let mut references = BTreeSet::new();
+        let mut referenced = HashSet::new();
+        for object in &self.objects {
+            for (index, section) in object.sections.iter().enumerate() {
+                let section = match section {
+                    Some(section) => section,
+                    None => continue,
+                };
+                for rel in &section.relocations {
+                    let symbol = &object.symbols[rel.symbol];
+                    if symbol.is_local() || !undefined.contains(symbol.name.as_str()) {
+                        continue;
+                    }
+                    referenced.insert(symbol.name.as_str());
+                    references.insert(UndefinedReference {
+                        symbol: symbol.name.clone(),
+                        location: object.location(Some(index)),
+                    });
+                }
+            }
+        }
+        // symbols which are only declared, without any relocation against them, unless
+        // `--gc-sections` may have discarded the relocations
+        for (name, sym) in self.symbols.undefined() {
+            if !undefined.contains(name) || referenced.contains(name) {
+                continue;
+            }
+            if let Some(id) = sym
+                .referenced_by
+                .filter(|id| !self.objects[id.0].gc_sections)
+            {
+                references.insert(UndefinedReference {
+                    symbol: name.to_owned(),
+                    location: self.objects[id.0].location(None),
+                });
+            }
+        }
+
+        let references: Vec<_> = references.into_iter().collect();
+        if references.is_empty() {
+            return Ok(());
+        }
+        if self.options.allow_undefined {
+            for reference in &references {
+                warn!("{}", reference);
+            }
+            return Ok(());
+        }
+        Err(LinkError::UndefinedSymbols(references))
+    }
+
+    /// Give every winning common symbol its own zero-initialized section.
+    fn allocate_commons(&mut self) {
+        let mut commons: Vec<_> = self
+            .symbols
+            .iter()
+            .filter_map(|(_, sym)| sym.definition)
+            .filter(|def| def.strength == Strength::Common)
+            .collect();
+        commons.sort_by_key(|def| (def.object, def.index));
+
+        for def in commons {
+            let object = &mut self.objects[def.object.0];
+            let symbol = &mut object.symbols[def.index];
+            let section = InputSection {
+                name: format!(".bss.{}", symbol.name),
+                sh_type: elf::SHT_NOBITS,
+                flags: elf::SHF_ALLOC | elf::SHF_WRITE,
+                // value of a common symbol is its alignment
+                align: symbol.value.max(1),
+                size: symbol.size,
+                data: Vec::new(),
+                link: 0,
+                relocations: Vec::new(),
+            };
+            symbol.section = SymbolSection::Section(object.sections.len());
+            symbol.value = 0;
+            object.sections.push(Some(section));
+        }
+    }
+
+    /// `e_entry` of the output, which locates the module info instead of the code to run.
+    fn entry(&self, layout: &Layout) -> u32 {
+        if let OutputOptions::Executable { .. } = self.input.output_options {
+            if self.start_symbol().is_none() {
+                warn!("cannot find entry symbol `{}`", ENTRY_SYMBOL);
+            }
+        }
+        self.module_info
+            .and_then(|id| layout.section_address(id))
+            .and_then(|address| layout.segment_offset(address))
+            .map_or(0, |(segment, offset)| {
+                (segment as u32) << ENTRY_SEGMENT_SHIFT | offset
+            })
+    }
+
+    /// Resolve, lay out, relocate and write the output file.
+    pub fn finish(mut self) -> Result<(), LinkError> {
+        self.check_duplicates()?;
+        self.allocate_commons();
+        // references from collected sections do not need to be defined
+        self.collect_garbage();
+        self.prune_eh_frames();
+        // which may define a symbol the inputs refer to
+        self.reserve_eh_frame_hdr()?;
+        self.check_undefined()?;
+        self.add_tls_offsets();
+        self.add_module_info()?;
+        self.reserve_exidx()?;
+
+        let mut layout = self.layout_with_veneers();
+        let dynamic_relocations = self.relocate(&mut layout)?;
+        self.sort_exidx(&mut layout)?;
+        self.write_eh_frame_hdr(&mut layout)?;
+        self.write_module_info(&mut layout);
+        self.write_variable_relocations(&mut layout);
+        debug!(
+            "Output sections: {:#?}",
+            layout
+                .sections
+                .iter()
+                .map(|s| (&s.name, s.address, s.size))
+                .collect::<Vec<_>>()
+        );
+
+        let entry = self.entry(&layout);
+        writer::write(&self, &layout, entry, &dynamic_relocations)?;
+        match &self.input.map_file {
+            Some(path) => map::write_map(&self, &layout, path),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,163 @@
+//! `SceModuleInfo`, the header through which the loader finds everything else in a module.
+
+use super::{
+    exidx::EXIDX_SECTION,
+    exports::{self, EXPORTS_SECTION},
+    imports::{self, IMPORTS_SECTION},
+    synthetic::SyntheticObject,
+    Layout, LinkError, Linker, SectionId, SymbolValue, ENTRY_SYMBOL,
+};
+use crate::input::OutputOptions;
+use bytemuck::{bytes_of, Zeroable};
+use log::debug;
+use object::elf;
+use psvita_sce_types::{
+    module_info::{
+        RawAttributes, SceModuleAttribute, SceModuleInfo, SceModulePrivilegeLevel,
+        MODULE_NAME_MAX_LEN,
+    },
+    nid::Nid,
+    Ptr, PtrRange,
+};
+use std::mem;
+
+/// Section holding the module info, which has to be in the first segment.
+pub const MODULE_INFO_SECTION: &str = ".sceModuleInfo.rodata";
+
+/// Value of entries which the module does not have.
+const NO_ENTRY: u32 = u32::MAX;
+
+impl Linker<'_> {
+    /// Symbol which the module starts at, `module_start` or the entry of executables.
+    pub fn start_symbol(&self) -> Option<&str> {
+        if self.symbols.definition("module_start").is_some() {
+            return Some("module_start");
+        }
+        match self.input.output_options {
+            OutputOptions::Executable { .. } if self.symbols.definition(ENTRY_SYMBOL).is_some() => {
+                Some(ENTRY_SYMBOL)
+            }
+            _ => None,
+        }
+    }
+
+    /// Add the object holding the module info, the export and the import tables along with
+    /// the function stubs.
+    ///
+    /// The module info itself is only filled by [`Linker::write_module_info`], once the
+    /// layout is known.
+    pub fn add_module_info(&mut self) -> Result<(), LinkError> {
+        let mut object = SyntheticObject::new("<module info>");
+        let section = object.add_section(MODULE_INFO_SECTION, 0, 4);
+        let info = SceModuleInfo::zeroed();
+        object.append(section, bytes_of(&info), 4);
+        object.define(
+            "module_info",
+            section,
+            0,
+            mem::size_of::<SceModuleInfo>() as u32,
+            elf::STT_OBJECT,
+        );
+        let mut imports = self.import_libraries();
+        self.add_function_stubs(&mut object, &imports);
+        self.take_variable_references(&mut imports)?;
+        let exports = self.export_libraries();
+        exports::add_export_tables(&mut object, &exports);
+        let variable_relocations = imports::add_import_tables(&mut object, &mut imports);
+        self.exports = exports;
+        self.imports = imports;
+
+        let object = self.add_object(object.into_object())?;
+        self.module_info = Some(SectionId {
+            object,
+            index: section,
+        });
+        self.variable_relocations = variable_relocations.map(|index| SectionId { object, index });
+        Ok(())
+    }
+
+    /// Name of the module, taken from the output file name.
+    pub fn module_name(&self) -> String {
+        self.input
+            .output_file
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    /// Fill the module info in the laid out image.
+    pub fn write_module_info(&self, layout: &mut Layout) {
+        let id = match self.module_info {
+            Some(id) => id,
+            None => return,
+        };
+
+        let mut info = SceModuleInfo::zeroed();
+        let name = self.module_name();
+        // keep the terminating NUL
+        let len = name.len().min(MODULE_NAME_MAX_LEN - 1);
+        info.common.name[..len].copy_from_slice(&name.as_bytes()[..len]);
+        info.common.attributes =
+            RawAttributes::new(SceModuleAttribute::empty(), SceModulePrivilegeLevel::USER);
+        info.common.module_version = [1, 1];
+        info.common.info_version = 6;
+        info.debug_fingerprint.0 = Nid::generate(name.as_bytes()).0;
+
+        let exports = section_range(layout, EXPORTS_SECTION);
+        info.public_api.exports = ptr_range(exports);
+        info.public_api.imports = ptr_range(
+            section_range(layout, IMPORTS_SECTION).or_else(|| exports.map(|(_, end)| (end, end))),
+        );
+
+        if let Some(tls) = &layout.tls {
+            if let Some((_, start)) = layout.segment_offset(tls.address) {
+                info.tls.tls_start = Ptr::new(start);
+                info.tls.tls_filesz = tls.file_size;
+                info.tls.tls_memsz = tls.mem_size;
+            }
+        }
+
+        let entry = |name: Option<&str>| {
+            name.and_then(|name| self.global_value(layout, name))
+                .and_then(|v| layout.segment_offset(v.address | v.thumb as u32))
+                .map_or(NO_ENTRY, |(_, offset)| offset)
+        };
+        info.entries.start_entry = Ptr::new(entry(self.start_symbol()));
+        info.entries.stop_entry = Ptr::new(entry(
+            self.symbols
+                .definition("module_stop")
+                .map(|_| "module_stop"),
+        ));
+
+        info.arm_exidx.0 = ptr_range(section_range(layout, EXIDX_SECTION));
+        info.arm_extab.0 = ptr_range(section_range(layout, ".ARM.extab"));
+
+        debug!("module info: {:#x?}", info);
+        let (output, offset) = layout.placement(id).unwrap();
+        let offset = offset as usize;
+        layout.sections[output].data[offset..offset + mem::size_of::<SceModuleInfo>()]
+            .copy_from_slice(bytes_of(&info));
+    }
+
+    /// Value of the definition of a global symbol.
+    pub fn global_value(&self, layout: &Layout, name: &str) -> Option<SymbolValue> {
+        let def = self.symbols.definition(name)?;
+        self.symbol_value(layout, def.object, def.index)
+            .filter(|v| !v.absolute)
+    }
+}
+
+/// Segment offsets of the start and the end of an output section.
+fn section_range(layout: &Layout, name: &str) -> Option<(u32, u32)> {
+    let section = &layout.sections[layout.find_section(name)?];
+    let (_, start) = layout.segment_offset(section.address)?;
+    Some((start, start + section.size))
+}
+
+fn ptr_range<T>(range: Option<(u32, u32)>) -> PtrRange<T> {
+    let (top, bottom) = range.unwrap_or((0, 0));
+    PtrRange {
+        top: Ptr::new(top),
+        bottom: Ptr::new(bottom),
+    }
+}
@@ -0,0 +1,286 @@
+//! Relocatable ELF objects, copied out of the input file into owned structures.
+
+use super::{LinkError, SymbolLocation};
+use crate::codegen::{verification, VitaEndian, VITA_ENDIAN};
+use object::{
+    elf,
+    read::elf::{FileHeader, Rel, Rela, SectionHeader, Sym},
+};
+
+pub type FileHeader32 = elf::FileHeader32<VitaEndian>;
+
+#[derive(Debug)]
+pub struct ObjectFile {
+    /// Name used in diagnostics, `archive(member)` for archive members.
+    pub name: String,
+    pub gc_sections: bool,
+    /// Stubs of the object are only imported when referenced, set by `--as-needed`.
+    pub as_needed: bool,
+    /// Indexed by ELF section index, `None` for sections which do not get linked.
+    pub sections: Vec<Option<InputSection>>,
+    /// Indexed by ELF symbol index.
+    pub symbols: Vec<InputSymbol>,
+    /// COMDAT groups as pairs of a signature and member section indices.
+    pub comdat_groups: Vec<(String, Vec<usize>)>,
+}
+
+#[derive(Debug)]
+pub struct InputSection {
+    pub name: String,
+    pub sh_type: u32,
+    pub flags: u32,
+    pub align: u32,
+    pub size: u32,
+    /// Empty for `SHT_NOBITS` sections.
+    pub data: Vec<u8>,
+    pub link: u32,
+    pub relocations: Vec<Relocation>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Relocation {
+    pub offset: u32,
+    pub r_type: u32,
+    pub symbol: usize,
+    /// Addend of a `SHT_RELA` entry, `SHT_REL` addends are stored at the place itself.
+    pub addend: Option<i32>,
+}
+
+#[derive(Debug, Clone)]
+pub struct InputSymbol {
+    pub name: String,
+    pub value: u32,
+    pub size: u32,
+    pub kind: u8,
+    pub binding: u8,
+    pub visibility: u8,
+    pub section: SymbolSection,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolSection {
+    Undefined,
+    Absolute,
+    Common,
+    Section(usize),
+}
+
+impl InputSymbol {
+    pub fn is_local(&self) -> bool {
+        self.binding == elf::STB_LOCAL
+    }
+
+    pub fn is_weak(&self) -> bool {
+        self.binding == elf::STB_WEAK
+    }
+
+    pub fn is_undefined(&self) -> bool {
+        self.section == SymbolSection::Undefined
+    }
+
+    /// Whether the symbol is a Thumb function, encoded into the lowest bit of its value.
+    pub fn is_thumb(&self) -> bool {
+        self.kind == elf::STT_FUNC && self.value & 1 != 0
+    }
+
+    /// Symbol value without the Thumb bit.
+    pub fn offset(&self) -> u32 {
+        if self.is_thumb() {
+            self.value & !1
+        } else {
+            self.value
+        }
+    }
+}
+
+impl InputSection {
+    pub fn is_alloc(&self) -> bool {
+        self.flags & elf::SHF_ALLOC != 0
+    }
+
+    pub fn is_nobits(&self) -> bool {
+        self.sh_type == elf::SHT_NOBITS
+    }
+}
+
+impl ObjectFile {
+    pub fn parse(
+        name: String,
+        data: &[u8],
+        gc_sections: bool,
+        as_needed: bool,
+    ) -> Result<Self, LinkError> {
+        let endian = VITA_ENDIAN;
+        let header = FileHeader32::parse(data).map_err(LinkError::parse(&name))?;
+        verification::validate_header(header).map_err(|source| LinkError::BadHeader {
+            name: name.clone(),
+            source,
+        })?;
+        if header.e_type(endian) != elf::ET_REL {
+            return Err(LinkError::UnsupportedInput {
+                name,
+                reason: "not a relocatable object",
+            });
+        }
+
+        let section_table = header
+            .sections(endian, data)
+            .map_err(LinkError::parse(&name))?;
+        let symbol_table = section_table
+            .symbols(endian, data, elf::SHT_SYMTAB)
+            .map_err(LinkError::parse(&name))?;
+
+        let mut sections = Vec::with_capacity(section_table.len());
+        let mut comdat_groups = Vec::new();
+        for section in section_table.iter() {
+            let section_name = section_table
+                .section_name(endian, section)
+                .map_err(LinkError::parse(&name))?;
+            let section_name = String::from_utf8_lossy(section_name).into_owned();
+
+            if let Some((flag, members)) = section
+                .group(endian, data)
+                .map_err(LinkError::parse(&name))?
+            {
+                if flag & elf::GRP_COMDAT != 0 {
+                    let signature = symbol_table
+                        .symbol(section.sh_info(endian) as usize)
+                        .and_then(|sym| symbol_table.symbol_name(endian, sym))
+                        .map_err(LinkError::parse(&name))?;
+                    comdat_groups.push((
+                        String::from_utf8_lossy(signature).into_owned(),
+                        members.iter().map(|m| m.get(endian) as usize).collect(),
+                    ));
+                }
+                sections.push(None);
+                continue;
+            }
+
+            let sh_type = section.sh_type(endian);
+            let flags = section.sh_flags(endian);
+            if !is_linked(sh_type, flags, &section_name) {
+                sections.push(None);
+                continue;
+            }
+
+            let data = if sh_type == elf::SHT_NOBITS {
+                Vec::new()
+            } else {
+                section
+                    .data(endian, data)
+                    .map_err(LinkError::parse(&name))?
+                    .to_vec()
+            };
+            sections.push(Some(InputSection {
+                name: section_name,
+                sh_type,
+                flags,
+                align: section.sh_addralign(endian).max(1),
+                size: section.sh_size(endian),
+                data,
+                link: section.sh_link(endian),
+                relocations: Vec::new(),
+            }));
+        }
+
+        for section in section_table.iter() {
+            let target = section.sh_info(endian) as usize;
+            let mut relocations = Vec::new();
+            if let Some(rels) = section.rel(endian, data).map_err(LinkError::parse(&name))? {
+                relocations.extend(rels.iter().map(|rel| Relocation {
+                    offset: rel.r_offset(endian),
+                    r_type: rel.r_type(endian),
+                    symbol: rel.r_sym(endian) as usize,
+                    addend: None,
+                }));
+            }
+            if let Some(relas) = section
+                .rela(endian, data)
+                .map_err(LinkError::parse(&name))?
+            {
+                relocations.extend(relas.iter().map(|rela| Relocation {
+                    offset: rela.r_offset(endian),
+                    r_type: rela.r_type(endian),
+                    symbol: rela.r_sym(endian) as usize,
+                    addend: Some(rela.r_addend(endian)),
+                }));
+            }
+            if let Some(Some(target)) = sections.get_mut(target) {
+                target.relocations.append(&mut relocations);
+            }
+        }
+
+        let mut symbols = Vec::with_capacity(symbol_table.len());
+        for (index, sym) in symbol_table.iter().enumerate() {
+            let symbol_name = symbol_table
+                .symbol_name(endian, sym)
+                .map_err(LinkError::parse(&name))?;
+            let section = match sym.st_shndx(endian) {
+                elf::SHN_UNDEF => SymbolSection::Undefined,
+                elf::SHN_ABS => SymbolSection::Absolute,
+                elf::SHN_COMMON => SymbolSection::Common,
+                elf::SHN_XINDEX => match symbol_table.shndx(index) {
+                    Some(index) => SymbolSection::Section(index as usize),
+                    None => SymbolSection::Undefined,
+                },
+                index => SymbolSection::Section(index as usize),
+            };
+            symbols.push(InputSymbol {
+                name: String::from_utf8_lossy(symbol_name).into_owned(),
+                value: sym.st_value(endian),
+                size: sym.st_size(endian),
+                kind: sym.st_type(),
+                binding: sym.st_bind(),
+                visibility: sym.st_visibility(),
+                section,
+            });
+        }
+
+        Ok(ObjectFile {
+            name,
+            gc_sections,
+            as_needed,
+            sections,
+            symbols,
+            comdat_groups,
+        })
+    }
+
+    pub fn section(&self, index: usize) -> Option<&InputSection> {
+        self.sections.get(index).and_then(Option::as_ref)
+    }
+
+    /// Where the section with the given ELF index is, for diagnostics.
+    pub fn location(&self, section: Option<usize>) -> SymbolLocation {
+        SymbolLocation {
+            object: self.name.clone(),
+            section: section
+                .and_then(|index| self.section(index))
+                .map(|s| s.name.clone()),
+        }
+    }
+
+    /// Where the symbol with the given index is defined, for diagnostics.
+    pub fn symbol_location(&self, index: usize) -> SymbolLocation {
+        match self.symbols[index].section {
+            SymbolSection::Section(section) => self.location(Some(section)),
+            _ => self.location(None),
+        }
+    }
+}
+
+/// Decide whether the section contributes to the output.
+fn is_linked(sh_type: u32, flags: u32, name: &str) -> bool {
+    match sh_type {
+        elf::SHT_NULL
+        | elf::SHT_SYMTAB
+        | elf::SHT_STRTAB
+        | elf::SHT_REL
+        | elf::SHT_RELA
+        | elf::SHT_GROUP
+        | elf::SHT_SYMTAB_SHNDX
+        | elf::SHT_ARM_ATTRIBUTES => false,
+        _ if flags & elf::SHF_EXCLUDE != 0 => false,
+        _ => flags & elf::SHF_ALLOC != 0 || name.starts_with(".debug_"),
+    }
+}
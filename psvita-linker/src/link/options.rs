@@ -0,0 +1,59 @@
+//! Options of the link which come from `-z` keywords.
+
+use super::layout::SEGMENT_ALIGN;
+use ld_compat_args::ZKeyword;
+use log::warn;
+use std::convert::TryFrom;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LinkOptions {
+    /// Report undefined symbols as warnings, set by `-z undefs`.
+    pub allow_undefined: bool,
+    /// Keep the first of duplicate definitions, set by `-z muldefs`.
+    pub allow_multiple_definitions: bool,
+    /// Alignment of segment start addresses, set by `-z max-page-size=`.
+    pub segment_align: u32,
+}
+
+impl Default for LinkOptions {
+    fn default() -> Self {
+        LinkOptions {
+            allow_undefined: false,
+            allow_multiple_definitions: false,
+            segment_align: SEGMENT_ALIGN,
+        }
+    }
+}
+
+impl LinkOptions {
+    /// Apply the keywords in order, warning about the ones which do not mean anything here.
+    pub fn from_z_keywords(keywords: &[ZKeyword]) -> Self {
+        let mut options = LinkOptions::default();
+        for keyword in keywords {
+            match keyword {
+                ZKeyword::Defs(defs) => options.allow_undefined = !defs,
+                ZKeyword::MulDefs => options.allow_multiple_definitions = true,
+                ZKeyword::MaxPageSize(size) => match u32::try_from(*size) {
+                    Ok(size) if size.is_power_of_two() => options.segment_align = size,
+                    _ => warn!("ignoring `-z {}`, not a power of two", keyword),
+                },
+                // the loader binds imports when loading, never runs the stack and patches
+                // any segment
+                ZKeyword::ExecStack(false) | ZKeyword::Now(true) | ZKeyword::Text(false) => (),
+                ZKeyword::ExecStack(true) => {
+                    warn!("ignoring `-z execstack`, stacks are never executable on psvita")
+                }
+                ZKeyword::StackSize(_) => warn!(
+                    "ignoring `-z {}`, the stack size comes from `sceUserMainThreadStackSize`",
+                    keyword
+                ),
+                ZKeyword::Unknown(keyword) => warn!("ignoring unknown `-z {}`", keyword),
+                _ => warn!(
+                    "ignoring `-z {}`, it has no effect on psvita modules",
+                    keyword
+                ),
+            }
+        }
+        options
+    }
+}
@@ -0,0 +1,440 @@
+//! Application of static relocations to the laid out image.
+
+use super::{
+    tls, Layout, LinkError, Linker, ObjectId, Relocation, SectionId, SymbolSection, SymbolValue,
+};
+use bytemuck::bytes_of;
+use log::warn;
+use object::elf;
+use psvita_sce_types::relocation::{SceRelocationLong, SceRelocationShort};
+use std::convert::TryInto;
+
+/// `R_ARM_THM_CALL`, which the `object` crate still calls by its old name.
+pub(crate) const R_ARM_THM_CALL: u32 = elf::R_ARM_THM_PC22;
+
+/// Relocation which has to be repeated by the loader after the module is moved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DynamicRelocation {
+    /// `R_ARM_ABS32`, `R_ARM_MOVW_ABS_NC`, `R_ARM_MOVT_ABS` or their Thumb forms.
+    pub r_type: u32,
+    /// Address of the relocated word.
+    pub place: u32,
+    /// Address the relocated word refers to.
+    pub target: u32,
+}
+
+/// Why a relocation could not be applied.
+pub(crate) enum Failure {
+    Overflow,
+    /// Branch without a link cannot switch between ARM and Thumb.
+    Interworking,
+    Unsupported,
+}
+
+impl Linker<'_> {
+    /// Apply relocations of every placed section, return relocations left for the loader.
+    pub fn relocate(&self, layout: &mut Layout) -> Result<Vec<DynamicRelocation>, LinkError> {
+        let relocatable = self.is_relocatable_output();
+        let mut dynamic = Vec::new();
+
+        for (object_index, object) in self.objects.iter().enumerate() {
+            for (index, section) in object.sections.iter().enumerate() {
+                let section = match section {
+                    Some(s) => s,
+                    None => continue,
+                };
+                let id = SectionId {
+                    object: ObjectId(object_index),
+                    index,
+                };
+                let (output_index, section_offset) = match layout.placement(id) {
+                    Some(p) => p,
+                    None => continue,
+                };
+
+                for rel in &section.relocations {
+                    if rel.r_type == elf::R_ARM_NONE {
+                        continue;
+                    }
+                    let mut target = self.symbol_value(layout, id.object, rel.symbol);
+                    if tls::is_tls(rel.r_type) {
+                        target = target.map(|t| tls::tls_value(layout, rel.r_type, t));
+                    }
+                    let undefined_weak_branch =
+                        is_branch(rel.r_type) && self.is_undefined_weak(id.object, rel.symbol);
+                    let veneer = if is_branch(rel.r_type) && !undefined_weak_branch {
+                        self.veneer_target(layout, id.object, section, rel)
+                    } else {
+                        None
+                    };
+                    let output = &mut layout.sections[output_index];
+                    let offset = (section_offset + rel.offset) as usize;
+                    let place = output.address + section_offset + rel.offset;
+                    let in_section = rel
+                        .offset
+                        .checked_add(4)
+                        .is_some_and(|end| end <= section.size);
+                    let word = match output.data.get_mut(offset..offset + 4) {
+                        Some(word) if in_section => word,
+                        _ => {
+                            return Err(LinkError::RelocationOutOfBounds {
+                                object: object.name.clone(),
+                                section: section.name.clone(),
+                                offset: rel.offset,
+                                r_type: rel.r_type,
+                            })
+                        }
+                    };
+                    // calls to undefined weak symbols fall through to the next instruction
+                    if undefined_weak_branch {
+                        word.copy_from_slice(&branch_nop(rel.r_type));
+                        continue;
+                    }
+                    let target = match target {
+                        Some(t) => t,
+                        // references into discarded sections get a tombstone value
+                        None => {
+                            if rel.r_type == elf::R_ARM_ABS32 {
+                                word.copy_from_slice(&0u32.to_le_bytes());
+                            }
+                            continue;
+                        }
+                    };
+
+                    let word: &mut [u8; 4] = word.try_into().unwrap();
+                    let mut result = apply(rel, word, place, target);
+                    if let (Err(Failure::Overflow | Failure::Interworking), Some(veneer)) =
+                        (&result, veneer)
+                    {
+                        result = apply(rel, word, place, veneer);
+                    }
+                    match result {
+                        Ok(Some(value)) => {
+                            if relocatable && section.is_alloc() && !target.absolute {
+                                let r_type = match rel.r_type {
+                                    elf::R_ARM_TARGET1 => elf::R_ARM_ABS32,
+                                    r_type => r_type,
+                                };
+                                dynamic.push(DynamicRelocation {
+                                    r_type,
+                                    place,
+                                    target: value,
+                                });
+                            }
+                        }
+                        Ok(None) => (),
+                        Err(failure) => {
+                            let object = object.name.clone();
+                            let section = section.name.clone();
+                            return Err(match failure {
+                                Failure::Overflow | Failure::Interworking => {
+                                    LinkError::RelocationOverflow {
+                                        object,
+                                        section,
+                                        offset: rel.offset,
+                                        symbol: self.symbol_name(id.object, rel.symbol),
+                                        r_type: rel.r_type,
+                                    }
+                                }
+                                Failure::Unsupported => LinkError::UnsupportedRelocation {
+                                    object,
+                                    section,
+                                    r_type: rel.r_type,
+                                },
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        self.write_veneers(layout);
+        Ok(dynamic)
+    }
+
+    /// Whether the symbol is only referenced weakly and nothing defines it.
+    pub(crate) fn is_undefined_weak(&self, object: ObjectId, index: usize) -> bool {
+        let symbol = &self.objects[object.0].symbols[index];
+        self.resolve_symbol(object, index).is_none()
+            && self
+                .symbols
+                .get(&symbol.name)
+                .is_some_and(|sym| sym.weak_reference)
+    }
+
+    /// Name of a symbol for diagnostics, section symbols are named after their section.
+    pub fn symbol_name(&self, object: ObjectId, index: usize) -> String {
+        let object = &self.objects[object.0];
+        let symbol = &object.symbols[index];
+        match symbol.section {
+            SymbolSection::Section(section) if symbol.kind == elf::STT_SECTION => object
+                .section(section)
+                .map_or_else(String::new, |s| s.name.clone()),
+            _ => symbol.name.clone(),
+        }
+    }
+}
+
+/// Encode relocations for the loader in the SCE format, using the short form where it fits.
+pub fn sce_relocations(layout: &Layout, relocations: &[DynamicRelocation]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for rel in relocations {
+        let (datseg, offset) = match layout.segment_offset(rel.place) {
+            Some(p) => p,
+            None => {
+                warn!(
+                    "relocation at {:#010x} is outside of every segment",
+                    rel.place
+                );
+                continue;
+            }
+        };
+        // the Thumb bit stays in the addend
+        let (symseg, addend) = match layout.segment_offset(rel.target & !1) {
+            Some((segment, offset)) => (segment, offset | rel.target & 1),
+            None => {
+                warn!(
+                    "relocation at {:#010x} targets {:#010x}, outside of every segment",
+                    rel.place, rel.target
+                );
+                continue;
+            }
+        };
+        let (symseg, code, datseg) = (symseg as u8, rel.r_type as u8, datseg as u8);
+        match SceRelocationShort::new(symseg, code, datseg, offset, addend) {
+            Some(short) => out.extend_from_slice(bytes_of(&short)),
+            None => match SceRelocationLong::new(symseg, code, datseg, offset, addend) {
+                Some(long) => out.extend_from_slice(bytes_of(&long)),
+                None => warn!("too many segments to relocate {:#010x}", rel.place),
+            },
+        }
+    }
+    out
+}
+
+/// Apply a single relocation to the 4 bytes at `place`.
+///
+/// Returns the absolute target address for relocations which depend on the load address.
+pub(crate) fn apply(
+    rel: &Relocation,
+    word: &mut [u8; 4],
+    place: u32,
+    target: SymbolValue,
+) -> Result<Option<u32>, Failure> {
+    let s = target.address;
+    let t = target.thumb as u32;
+    let insn = u32::from_le_bytes(*word);
+    let (hw1, hw2) = (insn as u16, (insn >> 16) as u16);
+    let addend = |implicit: u32| rel.addend.map_or(implicit, |a| a as u32);
+
+    match rel.r_type {
+        elf::R_ARM_NONE | elf::R_ARM_V4BX => (),
+        elf::R_ARM_ABS32 | elf::R_ARM_TARGET1 => {
+            let value = s.wrapping_add(addend(insn)) | t;
+            *word = value.to_le_bytes();
+            return Ok(Some(value));
+        }
+        elf::R_ARM_TLS_LE32
+        | elf::R_ARM_TLS_TPOFF32
+        | elf::R_ARM_TLS_LDO32
+        | elf::R_ARM_TLS_DTPOFF32 => {
+            *word = s.wrapping_add(addend(insn)).to_le_bytes();
+        }
+        elf::R_ARM_REL32 | elf::R_ARM_TARGET2 => {
+            let value = (s.wrapping_add(addend(insn)) | t).wrapping_sub(place);
+            *word = value.to_le_bytes();
+        }
+        elf::R_ARM_PREL31 => {
+            let value = (s.wrapping_add(addend(sign_extend(insn, 31))) | t).wrapping_sub(place);
+            check_signed(value, 31)?;
+            *word = ((insn & 0x8000_0000) | (value & 0x7FFF_FFFF)).to_le_bytes();
+        }
+        elf::R_ARM_CALL | elf::R_ARM_JUMP24 => {
+            if rel.r_type == elf::R_ARM_JUMP24 && t != 0 {
+                return Err(Failure::Interworking);
+            }
+            let value = (s.wrapping_add(addend(arm_branch_offset(insn))) | t).wrapping_sub(place);
+            check_signed(value, 26)?;
+            let insn = if t != 0 {
+                // `BL` to Thumb code becomes `BLX`, with bit 1 of the offset in the H bit
+                0xFA00_0000 | (value & 2) << 23
+            } else if insn >> 28 == 0xF {
+                // and `BLX` to ARM code becomes `BL`
+                0xEB00_0000
+            } else {
+                insn & 0xFF00_0000
+            };
+            *word = (insn | (value >> 2) & 0x00FF_FFFF).to_le_bytes();
+        }
+        R_ARM_THM_CALL | elf::R_ARM_THM_JUMP24 => {
+            if rel.r_type == elf::R_ARM_THM_JUMP24 && t == 0 {
+                return Err(Failure::Interworking);
+            }
+            let a = addend(thumb_branch_offset(hw1, hw2));
+            let (value, hw2) = if t != 0 {
+                ((s.wrapping_add(a) | t).wrapping_sub(place), hw2 | 0x1000)
+            } else {
+                // `BLX` to ARM code is relative to the word aligned PC
+                (
+                    s.wrapping_add(a).wrapping_sub(place & !3) & !3,
+                    hw2 & !0x1000,
+                )
+            };
+            check_signed(value, 25)?;
+            let (hw1, hw2) = encode_thumb_branch(hw1, hw2, value);
+            *word = join_halfwords(hw1, hw2);
+        }
+        elf::R_ARM_MOVW_ABS_NC | elf::R_ARM_MOVT_ABS => {
+            let value = s.wrapping_add(addend(sign_extend(arm_mov_imm(insn), 16))) | t;
+            let imm = mov_half(rel.r_type, value);
+            *word = (insn & 0xFFF0_F000 | (imm & 0xF000) << 4 | (imm & 0x0FFF)).to_le_bytes();
+            return Ok(Some(value));
+        }
+        elf::R_ARM_MOVW_PREL_NC | elf::R_ARM_MOVT_PREL => {
+            let value = (s.wrapping_add(addend(sign_extend(arm_mov_imm(insn), 16))) | t)
+                .wrapping_sub(place);
+            let imm = mov_half(rel.r_type, value);
+            *word = (insn & 0xFFF0_F000 | (imm & 0xF000) << 4 | (imm & 0x0FFF)).to_le_bytes();
+        }
+        elf::R_ARM_THM_MOVW_ABS_NC | elf::R_ARM_THM_MOVT_ABS => {
+            let implicit = sign_extend(thumb_mov_imm(hw1, hw2), 16);
+            let value = s.wrapping_add(addend(implicit)) | t;
+            let (hw1, hw2) = encode_thumb_mov(hw1, hw2, mov_half(rel.r_type, value));
+            *word = join_halfwords(hw1, hw2);
+            return Ok(Some(value));
+        }
+        elf::R_ARM_THM_MOVW_PREL_NC | elf::R_ARM_THM_MOVT_PREL => {
+            let implicit = sign_extend(thumb_mov_imm(hw1, hw2), 16);
+            let value = (s.wrapping_add(addend(implicit)) | t).wrapping_sub(place);
+            let (hw1, hw2) = encode_thumb_mov(hw1, hw2, mov_half(rel.r_type, value));
+            *word = join_halfwords(hw1, hw2);
+        }
+        _ => return Err(Failure::Unsupported),
+    }
+    Ok(None)
+}
+
+/// Addend of a `SHT_REL` relocation, stored in the relocated `word`.
+///
+/// Only meaningful for absolute data and move relocations.
+pub(crate) fn implicit_addend(r_type: u32, word: u32) -> u32 {
+    match r_type {
+        elf::R_ARM_MOVW_ABS_NC | elf::R_ARM_MOVT_ABS => sign_extend(arm_mov_imm(word), 16),
+        elf::R_ARM_THM_MOVW_ABS_NC | elf::R_ARM_THM_MOVT_ABS => {
+            sign_extend(thumb_mov_imm(word as u16, (word >> 16) as u16), 16)
+        }
+        _ => word,
+    }
+}
+
+/// Whether the relocation is a branch which a veneer can extend.
+pub(crate) fn is_branch(r_type: u32) -> bool {
+    matches!(
+        r_type,
+        elf::R_ARM_CALL | elf::R_ARM_JUMP24 | R_ARM_THM_CALL | elf::R_ARM_THM_JUMP24
+    )
+}
+
+/// `NOP` replacing a branch of the given relocation type, as the ARM ELF ABI requires for
+/// branches to undefined weak symbols.
+pub(crate) fn branch_nop(r_type: u32) -> [u8; 4] {
+    match r_type {
+        R_ARM_THM_CALL | elf::R_ARM_THM_JUMP24 => join_halfwords(0xF3AF, 0x8000),
+        _ => 0xE320_F000_u32.to_le_bytes(),
+    }
+}
+
+/// Offset from a branch to its destination, with the PC bias of the caller's mode removed.
+///
+/// Only meaningful for relocations accepted by [`is_branch`].
+pub(crate) fn branch_offset(rel: &Relocation, data: &[u8]) -> u32 {
+    let insn = data
+        .get(rel.offset as usize..rel.offset as usize + 4)
+        .map_or(0, |word| u32::from_le_bytes(word.try_into().unwrap()));
+    match rel.r_type {
+        elf::R_ARM_CALL | elf::R_ARM_JUMP24 => rel
+            .addend
+            .map_or_else(|| arm_branch_offset(insn), |a| a as u32)
+            .wrapping_add(8),
+        _ => rel
+            .addend
+            .map_or_else(
+                || thumb_branch_offset(insn as u16, (insn >> 16) as u16),
+                |a| a as u32,
+            )
+            .wrapping_add(4),
+    }
+}
+
+pub(crate) fn sign_extend(value: u32, bits: u32) -> u32 {
+    let shift = 32 - bits;
+    (((value << shift) as i32) >> shift) as u32
+}
+
+/// Fail unless `value` fits into a signed integer of `bits` width.
+fn check_signed(value: u32, bits: u32) -> Result<(), Failure> {
+    if sign_extend(value, bits) == value {
+        Ok(())
+    } else {
+        Err(Failure::Overflow)
+    }
+}
+
+fn join_halfwords(hw1: u16, hw2: u16) -> [u8; 4] {
+    (hw1 as u32 | (hw2 as u32) << 16).to_le_bytes()
+}
+
+/// Offset encoded into an ARM `B`, `BL` or `BLX` instruction.
+pub(crate) fn arm_branch_offset(insn: u32) -> u32 {
+    let offset = sign_extend((insn & 0x00FF_FFFF) << 2, 26);
+    if insn >> 28 == 0xF {
+        // `BLX` keeps a halfword offset in its H bit
+        offset | (insn >> 23) & 2
+    } else {
+        offset
+    }
+}
+
+/// Offset encoded into a Thumb-2 `BL`, `BLX` or `B.W` instruction.
+pub(crate) fn thumb_branch_offset(hw1: u16, hw2: u16) -> u32 {
+    let (hw1, hw2) = (hw1 as u32, hw2 as u32);
+    let s = (hw1 >> 10) & 1;
+    let i1 = !((hw2 >> 13) ^ s) & 1;
+    let i2 = !((hw2 >> 11) ^ s) & 1;
+    let offset = s << 24 | i1 << 23 | i2 << 22 | (hw1 & 0x3FF) << 12 | (hw2 & 0x7FF) << 1;
+    sign_extend(offset, 25)
+}
+
+pub(crate) fn encode_thumb_branch(hw1: u16, hw2: u16, offset: u32) -> (u16, u16) {
+    let s = (offset >> 24) & 1;
+    let j1 = (!(offset >> 23) ^ s) & 1;
+    let j2 = (!(offset >> 22) ^ s) & 1;
+    let hw1 = (hw1 as u32 & 0xF800) | s << 10 | (offset >> 12) & 0x3FF;
+    let hw2 = (hw2 as u32 & 0xD000) | j1 << 13 | j2 << 11 | (offset >> 1) & 0x7FF;
+    (hw1 as u16, hw2 as u16)
+}
+
+/// Half of the value loaded by `MOVW` or `MOVT`.
+fn mov_half(r_type: u32, value: u32) -> u32 {
+    match r_type {
+        elf::R_ARM_MOVT_ABS
+        | elf::R_ARM_MOVT_PREL
+        | elf::R_ARM_THM_MOVT_ABS
+        | elf::R_ARM_THM_MOVT_PREL => value >> 16,
+        _ => value & 0xFFFF,
+    }
+}
+
+fn arm_mov_imm(insn: u32) -> u32 {
+    (insn >> 4) & 0xF000 | insn & 0x0FFF
+}
+
+fn thumb_mov_imm(hw1: u16, hw2: u16) -> u32 {
+    let (hw1, hw2) = (hw1 as u32, hw2 as u32);
+    (hw1 & 0xF) << 12 | ((hw1 >> 10) & 1) << 11 | ((hw2 >> 12) & 7) << 8 | hw2 & 0xFF
+}
+
+fn encode_thumb_mov(hw1: u16, hw2: u16, imm: u32) -> (u16, u16) {
+    let hw1 = (hw1 as u32 & 0xFBF0) | (imm >> 12) & 0xF | ((imm >> 11) & 1) << 10;
+    let hw2 = (hw2 as u32 & 0x8F00) | ((imm >> 8) & 7) << 12 | imm & 0xFF;
+    (hw1 as u16, hw2 as u16)
+}
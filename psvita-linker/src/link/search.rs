@@ -0,0 +1,45 @@
+//! Search of `-l` libraries in `-L` directories.
+
+use super::LinkError;
+use crate::input::InputLibrary;
+use ld_compat_args::Library;
+use std::path::PathBuf;
+
+/// Find the file of the `library` the way GNU ld does.
+///
+/// `-l:file` names the file exactly, while `-lname` tries the `libname.vso` and
+/// `libname.suprx` modules followed by the `libname.a` archive in every directory in order.
+/// With `-Bstatic` in effect, only the archive is tried.
+pub fn find_library(
+    library: &InputLibrary,
+    search_paths: &[PathBuf],
+) -> Result<PathBuf, LinkError> {
+    let candidates = match &library.lib {
+        Library::File(file) => vec![file.clone()],
+        Library::Name(name) if library.only_static => vec![format!("lib{}.a", name)],
+        Library::Name(name) => vec![
+            format!("lib{}.vso", name),
+            format!("lib{}.suprx", name),
+            format!("lib{}.a", name),
+        ],
+    };
+
+    let mut tried = Vec::new();
+    for dir in search_paths {
+        for candidate in &candidates {
+            let path = dir.join(candidate);
+            if path.is_file() {
+                return Ok(path);
+            }
+            tried.push(path);
+        }
+    }
+
+    Err(LinkError::LibraryNotFound {
+        library: match &library.lib {
+            Library::File(file) => format!("-l:{}", file),
+            Library::Name(name) => format!("-l{}", name),
+        },
+        tried,
+    })
+}
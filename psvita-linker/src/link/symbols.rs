@@ -0,0 +1,166 @@
+//! Global symbol resolution.
+
+use super::{DuplicateDefinition, InputSymbol, ObjectFile, ObjectId, SymbolSection};
+use log::warn;
+use object::elf;
+use std::collections::HashMap;
+
+#[derive(Debug, Default)]
+pub struct SymbolTable {
+    globals: HashMap<String, GlobalSymbol>,
+    /// Strong definitions which came after the first one, reported together once every
+    /// object is loaded.
+    duplicates: Vec<DuplicateDefinition>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct GlobalSymbol {
+    pub definition: Option<Definition>,
+    /// First object which referenced the symbol without defining it.
+    pub referenced_by: Option<ObjectId>,
+    /// Set while every reference to an undefined symbol is weak.
+    pub weak_reference: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Definition {
+    pub object: ObjectId,
+    pub index: usize,
+    pub strength: Strength,
+}
+
+/// Precedence of a definition, a stronger one replaces a weaker one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Strength {
+    Weak,
+    Common,
+    Strong,
+}
+
+impl Strength {
+    fn of(symbol: &InputSymbol) -> Strength {
+        if symbol.section == SymbolSection::Common {
+            Strength::Common
+        } else if symbol.is_weak() {
+            Strength::Weak
+        } else {
+            Strength::Strong
+        }
+    }
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&GlobalSymbol> {
+        self.globals.get(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &GlobalSymbol)> {
+        self.globals.iter().map(|(name, sym)| (name.as_str(), sym))
+    }
+
+    /// Definition of the named symbol, if any.
+    pub fn definition(&self, name: &str) -> Option<Definition> {
+        self.globals.get(name).and_then(|sym| sym.definition)
+    }
+
+    /// Define a symbol which the linker added to an already registered object.
+    pub fn insert_definition(&mut self, name: String, definition: Definition) {
+        self.globals.insert(
+            name,
+            GlobalSymbol {
+                definition: Some(definition),
+                referenced_by: None,
+                weak_reference: false,
+            },
+        );
+    }
+
+    /// Forget the definition of the named symbol, so that a later object can provide it.
+    pub fn remove_definition(&mut self, name: &str) -> Option<Definition> {
+        self.globals.get_mut(name)?.definition.take()
+    }
+
+    /// Names of symbols which are referenced but not defined yet.
+    pub fn undefined(&self) -> impl Iterator<Item = (&str, &GlobalSymbol)> {
+        self.iter().filter(|(_, sym)| sym.definition.is_none())
+    }
+
+    /// Duplicate strong definitions seen so far.
+    pub fn duplicates(&self) -> &[DuplicateDefinition] {
+        &self.duplicates
+    }
+
+    /// Merge global symbols of the `object`, which is not pushed into `objects` yet.
+    ///
+    /// The earlier of two strong definitions is kept. The later one is recorded in
+    /// [`duplicates`](Self::duplicates), or with `keep_duplicates` only reported as a warning.
+    pub fn add_object(
+        &mut self,
+        objects: &[ObjectFile],
+        id: ObjectId,
+        object: &ObjectFile,
+        keep_duplicates: bool,
+    ) {
+        for (index, symbol) in object.symbols.iter().enumerate() {
+            if symbol.is_local() || symbol.kind == elf::STT_SECTION || symbol.kind == elf::STT_FILE
+            {
+                continue;
+            }
+
+            let global = self
+                .globals
+                .entry(symbol.name.clone())
+                .or_insert(GlobalSymbol {
+                    definition: None,
+                    referenced_by: None,
+                    weak_reference: true,
+                });
+
+            if symbol.is_undefined() {
+                global.referenced_by.get_or_insert(id);
+                global.weak_reference &= symbol.is_weak();
+                continue;
+            }
+
+            let new = Definition {
+                object: id,
+                index,
+                strength: Strength::of(symbol),
+            };
+            let old = match &mut global.definition {
+                Some(old) => old,
+                slot @ None => {
+                    *slot = Some(new);
+                    continue;
+                }
+            };
+
+            match (old.strength, new.strength) {
+                (Strength::Strong, Strength::Strong) => {
+                    let duplicate = DuplicateDefinition {
+                        symbol: symbol.name.clone(),
+                        first: objects[old.object.0].symbol_location(old.index),
+                        second: object.symbol_location(index),
+                    };
+                    if keep_duplicates {
+                        warn!("{}", duplicate);
+                    } else {
+                        self.duplicates.push(duplicate);
+                    }
+                }
+                (Strength::Common, Strength::Common) => {
+                    let old_size = objects[old.object.0].symbols[old.index].size;
+                    if symbol.size > old_size {
+                        *old = new;
+                    }
+                }
+                (old_strength, new_strength) if new_strength > old_strength => *old = new,
+                _ => (),
+            }
+        }
+    }
+}
@@ -0,0 +1,179 @@
+//! Objects assembled by the linker itself, like the tables which the loader reads.
+
+use super::{InputSection, InputSymbol, ObjectFile, Relocation, SymbolSection};
+use object::elf;
+use std::collections::HashMap;
+
+/// Builder of an [`ObjectFile`] whose sections are generated instead of read from an input.
+#[derive(Debug)]
+pub struct SyntheticObject {
+    object: ObjectFile,
+    /// Named symbols, both defined here and referenced from elsewhere.
+    by_name: HashMap<String, usize>,
+    section_symbols: HashMap<usize, usize>,
+}
+
+impl SyntheticObject {
+    pub fn new(name: &str) -> Self {
+        SyntheticObject {
+            object: ObjectFile {
+                name: name.to_owned(),
+                gc_sections: false,
+                as_needed: false,
+                sections: Vec::new(),
+                symbols: Vec::new(),
+                comdat_groups: Vec::new(),
+            },
+            by_name: HashMap::new(),
+            section_symbols: HashMap::new(),
+        }
+    }
+
+    /// Add an empty allocated section with `SHF_*` flags besides `SHF_ALLOC`, returns its index.
+    pub fn add_section(&mut self, name: &str, flags: u32, align: u32) -> usize {
+        self.object.sections.push(Some(InputSection {
+            name: name.to_owned(),
+            sh_type: elf::SHT_PROGBITS,
+            flags: elf::SHF_ALLOC | flags,
+            align,
+            size: 0,
+            data: Vec::new(),
+            link: 0,
+            relocations: Vec::new(),
+        }));
+        self.object.sections.len() - 1
+    }
+
+    /// Append `data` to the section at `align`, returns the offset it was placed at.
+    pub fn append(&mut self, section: usize, data: &[u8], align: u32) -> u32 {
+        let section = self.section_mut(section);
+        let offset = super::align_up(section.size, align);
+        section.data.resize(offset as usize, 0);
+        section.data.extend_from_slice(data);
+        section.size = section.data.len() as u32;
+        section.align = section.align.max(align);
+        offset
+    }
+
+    /// Current size of the section, which is where the next data will be appended.
+    pub fn size(&self, section: usize) -> u32 {
+        self.object.section(section).unwrap().size
+    }
+
+    /// Define a local symbol at `offset` of the section, returns its index.
+    pub fn define(
+        &mut self,
+        name: &str,
+        section: usize,
+        offset: u32,
+        size: u32,
+        kind: u8,
+    ) -> usize {
+        self.define_with_binding(name, section, offset, size, kind, elf::STB_LOCAL)
+    }
+
+    /// Define a global symbol at `offset` of the section, returns its index.
+    ///
+    /// Any other definition of the symbol has to be removed before the object is added.
+    pub fn define_global(
+        &mut self,
+        name: &str,
+        section: usize,
+        offset: u32,
+        size: u32,
+        kind: u8,
+    ) -> usize {
+        self.define_with_binding(name, section, offset, size, kind, elf::STB_GLOBAL)
+    }
+
+    fn define_with_binding(
+        &mut self,
+        name: &str,
+        section: usize,
+        offset: u32,
+        size: u32,
+        kind: u8,
+        binding: u8,
+    ) -> usize {
+        let index = self.push_symbol(InputSymbol {
+            name: name.to_owned(),
+            value: offset,
+            size,
+            kind,
+            binding,
+            visibility: elf::STV_DEFAULT,
+            section: SymbolSection::Section(section),
+        });
+        self.by_name.insert(name.to_owned(), index);
+        index
+    }
+
+    /// Symbol index which refers to `name`, a local definition of this object or a global.
+    ///
+    /// Globals are referenced weakly, so that they never make the link fail by themselves.
+    pub fn reference(&mut self, name: &str) -> usize {
+        if let Some(&index) = self.by_name.get(name) {
+            return index;
+        }
+        let index = self.push_symbol(InputSymbol {
+            name: name.to_owned(),
+            value: 0,
+            size: 0,
+            kind: elf::STT_NOTYPE,
+            binding: elf::STB_WEAK,
+            visibility: elf::STV_DEFAULT,
+            section: SymbolSection::Undefined,
+        });
+        self.by_name.insert(name.to_owned(), index);
+        index
+    }
+
+    /// Relocate the word at `offset` of the section against the symbol.
+    pub fn relocate(&mut self, section: usize, offset: u32, r_type: u32, symbol: usize) {
+        self.section_mut(section).relocations.push(Relocation {
+            offset,
+            r_type,
+            symbol,
+            addend: None,
+        });
+    }
+
+    /// Store the address of `target_offset` in the target section into the word at `offset`.
+    pub fn point_to(&mut self, section: usize, offset: u32, target: usize, target_offset: u32) {
+        let symbol = self.section_symbol(target);
+        let start = offset as usize;
+        self.section_mut(section).data[start..start + 4]
+            .copy_from_slice(&target_offset.to_le_bytes());
+        self.relocate(section, offset, elf::R_ARM_ABS32, symbol);
+    }
+
+    fn section_symbol(&mut self, section: usize) -> usize {
+        if let Some(&index) = self.section_symbols.get(&section) {
+            return index;
+        }
+        let index = self.push_symbol(InputSymbol {
+            name: String::new(),
+            value: 0,
+            size: 0,
+            kind: elf::STT_SECTION,
+            binding: elf::STB_LOCAL,
+            visibility: elf::STV_DEFAULT,
+            section: SymbolSection::Section(section),
+        });
+        self.section_symbols.insert(section, index);
+        index
+    }
+
+    pub fn into_object(self) -> ObjectFile {
+        self.object
+    }
+
+    fn push_symbol(&mut self, symbol: InputSymbol) -> usize {
+        self.object.symbols.push(symbol);
+        self.object.symbols.len() - 1
+    }
+
+    fn section_mut(&mut self, index: usize) -> &mut InputSection {
+        self.object.sections[index].as_mut().unwrap()
+    }
+}
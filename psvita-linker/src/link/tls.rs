@@ -0,0 +1,158 @@
+//! Thread-local storage, laid out like the ARM variant 1 model of static executables.
+//!
+//! The thread pointer points at a control block of [`TCB_SIZE`] bytes, which is followed by
+//! a copy of the module's [`TlsTemplate`] aligned to the template's alignment. Every
+//! variable then lives at a fixed offset from the thread pointer, so TLS relocations
+//! resolve at link time.
+//!
+//! Code for the general and local dynamic models, which position independent objects use by
+//! default, calls `__tls_get_addr` with a GOT entry. The output being the only module with
+//! TLS, those entries are filled at link time with module index [`TLS_MODULE_INDEX`] and
+//! the offset of the variable in the TLS block.
+
+use super::{
+    align_up, InputSection, InputSymbol, Layout, Linker, Relocation, SymbolSection, SymbolValue,
+    TlsTemplate,
+};
+use log::debug;
+use object::elf;
+use std::collections::HashMap;
+
+/// Size of the thread control block preceding the TLS block.
+pub const TCB_SIZE: u32 = 8;
+
+/// Module index of the output in the GOT entries passed to `__tls_get_addr`.
+pub const TLS_MODULE_INDEX: u32 = 1;
+
+/// Section holding the GOT entries of TLS variables.
+const GOT_SECTION: &str = ".got";
+
+/// Whether the relocation refers to the offset of a variable in the TLS block.
+pub(crate) fn is_tls(r_type: u32) -> bool {
+    matches!(
+        r_type,
+        elf::R_ARM_TLS_LE32
+            | elf::R_ARM_TLS_TPOFF32
+            | elf::R_ARM_TLS_LDO32
+            | elf::R_ARM_TLS_DTPOFF32
+    )
+}
+
+/// Value which a TLS relocation resolves the symbol to, instead of its address.
+///
+/// Undefined weak symbols stay 0.
+pub(crate) fn tls_value(layout: &Layout, r_type: u32, value: SymbolValue) -> SymbolValue {
+    let tls = match &layout.tls {
+        Some(tls) if !value.absolute => tls,
+        _ => return value,
+    };
+    let offset = value.address.wrapping_sub(tls.address);
+    let address = match r_type {
+        elf::R_ARM_TLS_LDO32 | elf::R_ARM_TLS_DTPOFF32 => offset,
+        _ => thread_pointer_offset(tls).wrapping_add(offset),
+    };
+    SymbolValue {
+        address,
+        thumb: false,
+        absolute: true,
+    }
+}
+
+/// Offset of the template copy from the thread pointer.
+fn thread_pointer_offset(tls: &TlsTemplate) -> u32 {
+    align_up(TCB_SIZE, tls.align)
+}
+
+/// GOT entry which a TLS relocation refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum GotEntry {
+    /// Thread pointer offset of the variable, for `R_ARM_TLS_IE32`.
+    Offset(usize),
+    /// Module index and offset of the variable, for `R_ARM_TLS_GD32`.
+    Variable(usize),
+    /// Module index with a zero offset, for `R_ARM_TLS_LDM32`.
+    Module,
+}
+
+impl Linker<'_> {
+    /// Give every TLS variable referenced through the GOT an entry, and turn the
+    /// relocations into `R_ARM_REL32` against that entry.
+    ///
+    /// The entries are kept in a `.got` section of the referencing object, so that local
+    /// variables can be referred to as well.
+    pub(crate) fn add_tls_offsets(&mut self) {
+        for object in &mut self.objects {
+            let got_index = object.sections.len();
+            let mut got = InputSection {
+                name: GOT_SECTION.to_owned(),
+                sh_type: elf::SHT_PROGBITS,
+                flags: elf::SHF_ALLOC,
+                align: 4,
+                size: 0,
+                data: Vec::new(),
+                link: 0,
+                relocations: Vec::new(),
+            };
+            // symbol at the start of every entry
+            let mut slots = HashMap::new();
+            let symbols = &mut object.symbols;
+
+            for section in object.sections.iter_mut().flatten() {
+                for rel in &mut section.relocations {
+                    let entry = match rel.r_type {
+                        elf::R_ARM_TLS_IE32 => GotEntry::Offset(rel.symbol),
+                        elf::R_ARM_TLS_GD32 => GotEntry::Variable(rel.symbol),
+                        elf::R_ARM_TLS_LDM32 => GotEntry::Module,
+                        _ => continue,
+                    };
+                    let slot = *slots.entry(entry).or_insert_with(|| {
+                        let offset = got.size;
+                        let (words, relocation) = match entry {
+                            GotEntry::Offset(symbol) => {
+                                (vec![0], Some((offset, elf::R_ARM_TLS_TPOFF32, symbol)))
+                            }
+                            GotEntry::Variable(symbol) => (
+                                vec![TLS_MODULE_INDEX, 0],
+                                Some((offset + 4, elf::R_ARM_TLS_DTPOFF32, symbol)),
+                            ),
+                            GotEntry::Module => (vec![TLS_MODULE_INDEX, 0], None),
+                        };
+                        for word in &words {
+                            got.data.extend_from_slice(&word.to_le_bytes());
+                        }
+                        got.size += words.len() as u32 * 4;
+                        if let Some((offset, r_type, symbol)) = relocation {
+                            got.relocations.push(Relocation {
+                                offset,
+                                r_type,
+                                symbol,
+                                addend: None,
+                            });
+                        }
+                        symbols.push(InputSymbol {
+                            name: String::new(),
+                            value: offset,
+                            size: got.size - offset,
+                            kind: elf::STT_OBJECT,
+                            binding: elf::STB_LOCAL,
+                            visibility: elf::STV_DEFAULT,
+                            section: SymbolSection::Section(got_index),
+                        });
+                        symbols.len() - 1
+                    });
+                    rel.r_type = elf::R_ARM_REL32;
+                    rel.symbol = slot;
+                }
+            }
+
+            if !slots.is_empty() {
+                debug!(
+                    "{}: {} GOT entries for TLS variables",
+                    object.name,
+                    slots.len()
+                );
+                object.sections.push(Some(got));
+            }
+        }
+    }
+}
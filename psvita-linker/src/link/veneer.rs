@@ -0,0 +1,269 @@
+//! Veneers for branches which cannot reach their destination directly.
+//!
+//! A branch needs a veneer when its destination is out of range, or when a `B` switches
+//! between ARM and Thumb code. Calls with a link switch modes on their own, as
+//! [`relocation`](super::relocation) rewrites `BL` into `BLX` and back.
+
+use super::{
+    relocation::{self, Failure},
+    InputSection, InputSymbol, Layout, Linker, ObjectFile, ObjectId, Relocation, SectionId,
+    SymbolSection, SymbolValue,
+};
+use log::debug;
+use object::elf;
+use std::convert::TryInto;
+
+/// ARM: `ldr ip, [pc, #4]; add ip, pc, ip; bx ip; .word dest - .`
+const ARM_PIC_VENEER: [u32; 4] = [0xE59F_C004, 0xE08F_C00C, 0xE12F_FF1C, 0];
+/// ARM: `ldr pc, [pc, #-4]; .word dest`
+const ARM_ABS_VENEER: [u32; 2] = [0xE51F_F004, 0];
+/// Thumb: `ldr.w ip, [pc, #4]; add ip, pc; bx ip; .word dest - .`
+const THUMB_PIC_VENEER: [u16; 6] = [0xF8DF, 0xC004, 0x44FC, 0x4760, 0, 0];
+/// Thumb: `ldr.w pc, [pc, #0]; .word dest`
+const THUMB_ABS_VENEER: [u16; 4] = [0xF8DF, 0xF000, 0, 0];
+
+/// Destination of a veneer, shared by every branch which needs the same one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VeneerKey {
+    /// Resolved destination symbol.
+    pub object: ObjectId,
+    pub symbol: usize,
+    /// Offset from the symbol, without the PC bias.
+    pub offset: u32,
+    /// Whether the veneer is entered from Thumb code.
+    pub thumb: bool,
+}
+
+#[derive(Debug)]
+pub struct Veneer {
+    pub key: VeneerKey,
+    pub section: SectionId,
+    /// Local symbol naming the veneer, in the same object as its section.
+    pub symbol: usize,
+    /// Whether the destination does not move with the module, so the veneer holds its address.
+    pub absolute: bool,
+}
+
+impl Linker<'_> {
+    /// Lay out the image, adding veneers until every branch can reach its destination and
+    /// growing the space reserved for unwind index entries to cover them.
+    pub fn layout_with_veneers(&mut self) -> Layout {
+        loop {
+            let layout = Layout::new(&self.objects, self.options.segment_align);
+            let missing = self.missing_veneers(&layout);
+            if missing.is_empty() {
+                // the veneers may need unwind index entries of their own
+                if !self.grow_exidx_reserve() {
+                    return layout;
+                }
+                continue;
+            }
+            for (key, absolute) in missing {
+                self.add_veneer(key, absolute);
+            }
+        }
+    }
+
+    /// Veneers which branches in the `layout` need but do not have yet.
+    fn missing_veneers(&self, layout: &Layout) -> Vec<(VeneerKey, bool)> {
+        let mut missing = Vec::new();
+        for (id, section) in self.placed_sections(layout) {
+            for rel in &section.relocations {
+                if !relocation::is_branch(rel.r_type)
+                    || self.is_undefined_weak(id.object, rel.symbol)
+                {
+                    continue;
+                }
+                let key = self.veneer_key(id.object, section, rel);
+                if self.veneer_index.contains_key(&key) || missing.iter().any(|(k, _)| *k == key) {
+                    continue;
+                }
+                let target = match self.symbol_value(layout, id.object, rel.symbol) {
+                    Some(target) => target,
+                    None => continue,
+                };
+
+                let place = layout.section_address(id).unwrap() + rel.offset;
+                let offset = rel.offset as usize;
+                let mut word: [u8; 4] = match section.data.get(offset..offset + 4) {
+                    Some(word) => word.try_into().unwrap(),
+                    // reported by `relocate`
+                    None => continue,
+                };
+                if let Err(Failure::Overflow) | Err(Failure::Interworking) =
+                    relocation::apply(rel, &mut word, place, target)
+                {
+                    missing.push((key, target.absolute));
+                }
+            }
+        }
+        missing
+    }
+
+    fn placed_sections<'s>(
+        &'s self,
+        layout: &'s Layout,
+    ) -> impl Iterator<Item = (SectionId, &'s InputSection)> + 's {
+        self.objects
+            .iter()
+            .enumerate()
+            .flat_map(|(object, file)| {
+                file.sections
+                    .iter()
+                    .enumerate()
+                    .filter_map(move |(index, section)| {
+                        let id = SectionId {
+                            object: ObjectId(object),
+                            index,
+                        };
+                        Some((id, section.as_ref()?))
+                    })
+            })
+            .filter(move |(id, _)| layout.placement(*id).is_some())
+    }
+
+    fn veneer_key(&self, object: ObjectId, section: &InputSection, rel: &Relocation) -> VeneerKey {
+        let (object, symbol) = self
+            .resolve_symbol(object, rel.symbol)
+            .unwrap_or((object, rel.symbol));
+        VeneerKey {
+            object,
+            symbol,
+            offset: relocation::branch_offset(rel, &section.data),
+            thumb: matches!(
+                rel.r_type,
+                relocation::R_ARM_THM_CALL | elf::R_ARM_THM_JUMP24
+            ),
+        }
+    }
+
+    fn add_veneer(&mut self, key: VeneerKey, absolute: bool) {
+        let object = match self.veneer_object {
+            Some(object) => object,
+            None => {
+                self.objects.push(ObjectFile {
+                    name: String::from("<veneers>"),
+                    gc_sections: false,
+                    as_needed: false,
+                    sections: Vec::new(),
+                    symbols: Vec::new(),
+                    comdat_groups: Vec::new(),
+                });
+                let object = ObjectId(self.objects.len() - 1);
+                self.veneer_object = Some(object);
+                object
+            }
+        };
+
+        let data: Vec<u8> = match (key.thumb, absolute) {
+            (false, false) => ARM_PIC_VENEER
+                .iter()
+                .flat_map(|i| i.to_le_bytes())
+                .collect(),
+            (false, true) => ARM_ABS_VENEER
+                .iter()
+                .flat_map(|i| i.to_le_bytes())
+                .collect(),
+            (true, false) => THUMB_PIC_VENEER
+                .iter()
+                .flat_map(|i| i.to_le_bytes())
+                .collect(),
+            (true, true) => THUMB_ABS_VENEER
+                .iter()
+                .flat_map(|i| i.to_le_bytes())
+                .collect(),
+        };
+        let name = format!("__{}_veneer", self.symbol_name(key.object, key.symbol));
+        let file = &mut self.objects[object.0];
+        let index = file.sections.len();
+        file.sections.push(Some(InputSection {
+            name: String::from(".text.veneer"),
+            sh_type: elf::SHT_PROGBITS,
+            flags: elf::SHF_ALLOC | elf::SHF_EXECINSTR,
+            align: 4,
+            size: data.len() as u32,
+            data,
+            link: 0,
+            relocations: Vec::new(),
+        }));
+        let symbol = file.symbols.len();
+        file.symbols.push(InputSymbol {
+            name,
+            value: key.thumb as u32,
+            size: file.sections[index].as_ref().unwrap().size,
+            kind: elf::STT_FUNC,
+            binding: elf::STB_LOCAL,
+            visibility: elf::STV_DEFAULT,
+            section: SymbolSection::Section(index),
+        });
+
+        self.veneer_index.insert(key, self.veneers.len());
+        self.veneers.push(Veneer {
+            key,
+            section: SectionId { object, index },
+            symbol,
+            absolute,
+        });
+    }
+
+    /// Value to branch to instead of the destination of `rel`, if it has a veneer.
+    ///
+    /// The veneer address is adjusted by the branch offset, so that applying `rel` to the
+    /// returned value lands exactly on the veneer.
+    pub(crate) fn veneer_target(
+        &self,
+        layout: &Layout,
+        object: ObjectId,
+        section: &InputSection,
+        rel: &Relocation,
+    ) -> Option<SymbolValue> {
+        let key = self.veneer_key(object, section, rel);
+        let veneer = &self.veneers[*self.veneer_index.get(&key)?];
+        let address = layout.section_address(veneer.section)?;
+        Some(SymbolValue {
+            address: address.wrapping_sub(key.offset),
+            thumb: key.thumb,
+            absolute: false,
+        })
+    }
+
+    /// Store destinations into the laid out veneers.
+    pub(crate) fn write_veneers(&self, layout: &mut Layout) {
+        for veneer in &self.veneers {
+            let key = veneer.key;
+            let target = self
+                .symbol_value(layout, key.object, key.symbol)
+                .unwrap_or(SymbolValue {
+                    address: 0,
+                    thumb: false,
+                    absolute: true,
+                });
+            let destination = target.address.wrapping_add(key.offset) | target.thumb as u32;
+
+            let (output, offset) = layout.placement(veneer.section).unwrap();
+            let output = &mut layout.sections[output];
+            let size = self.objects[veneer.section.object.0]
+                .section(veneer.section.index)
+                .unwrap()
+                .size;
+            let literal = offset + size - 4;
+            let place = output.address + literal;
+            let value = if veneer.absolute {
+                destination
+            } else {
+                destination.wrapping_sub(place)
+            };
+            let literal = literal as usize;
+            output.data[literal..literal + 4].copy_from_slice(&value.to_le_bytes());
+
+            debug!(
+                "veneer `{}` at {:#010x} to {:#010x} ({} caller{})",
+                self.symbol_name(veneer.section.object, veneer.symbol),
+                output.address + offset,
+                destination,
+                if key.thumb { "Thumb" } else { "ARM" },
+                if veneer.absolute { ", absolute" } else { "" },
+            );
+        }
+    }
+}
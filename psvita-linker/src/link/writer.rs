@@ -0,0 +1,493 @@
+//! ELF output writer.
+//!
+//! Two images are written from the same layout. The output file leaves out the debug
+//! information and the symbol table, which go into a companion `<stem>.debug.elf` along with
+//! everything else, so that debuggers and symbolizers see the addresses of the output file.
+//! The output names its companion in a `.gnu_debuglink` section.
+
+use super::{
+    layout::align_up, DynamicRelocation, Layout, LinkError, Linker, ObjectId, SectionId,
+    SymbolSection, SymbolValue, EH_FRAME_HDR_SECTION,
+};
+use crate::{
+    codegen::{VitaEndian, VITA_ENDIAN},
+    input::OutputOptions,
+};
+use object::{
+    elf,
+    endian::{U16, U32},
+    pod::{bytes_of, Pod},
+};
+use psvita_sce_types::elf::{ET_SCE_EXEC, ET_SCE_RELEXEC, PT_SCE_RELA, SHT_SCE_RELA};
+use std::{
+    collections::HashMap,
+    fs, mem,
+    path::{Path, PathBuf},
+};
+
+type Sym32 = elf::Sym32<VitaEndian>;
+
+/// Deduplicating ELF string table.
+#[derive(Debug)]
+struct StringTable {
+    data: Vec<u8>,
+    offsets: HashMap<String, u32>,
+}
+
+impl StringTable {
+    fn new() -> Self {
+        StringTable {
+            data: vec![0],
+            offsets: HashMap::new(),
+        }
+    }
+
+    fn add(&mut self, s: &str) -> u32 {
+        if s.is_empty() {
+            return 0;
+        }
+        let data = &mut self.data;
+        *self.offsets.entry(s.to_owned()).or_insert_with(|| {
+            let offset = data.len() as u32;
+            data.extend_from_slice(s.as_bytes());
+            data.push(0);
+            offset
+        })
+    }
+}
+
+/// Section which is not a part of [`Layout`], written after the laid out sections.
+struct ExtraSection {
+    name: &'static str,
+    sh_type: u32,
+    link: u32,
+    info: u32,
+    align: u32,
+    entsize: u32,
+    data: Vec<u8>,
+}
+
+fn u16(v: u16) -> U16<VitaEndian> {
+    U16::new(VITA_ENDIAN, v)
+}
+
+fn u32(v: u32) -> U32<VitaEndian> {
+    U32::new(VITA_ENDIAN, v)
+}
+
+fn bytes_of_vec<T: Pod>(items: &[T]) -> Vec<u8> {
+    items
+        .iter()
+        .flat_map(|i| bytes_of(i).iter().copied())
+        .collect()
+}
+
+/// Build the symbol table, returns it along with the index of the first global symbol.
+fn symbol_table(
+    linker: &Linker<'_>,
+    layout: &Layout,
+    strings: &mut StringTable,
+) -> (Vec<Sym32>, u32) {
+    let output_index = |object: ObjectId, section: SymbolSection| match section {
+        SymbolSection::Section(index) => layout
+            .placement(SectionId { object, index })
+            .map(|(output, _)| output as u16 + 1),
+        SymbolSection::Absolute => Some(elf::SHN_ABS),
+        SymbolSection::Undefined | SymbolSection::Common => Some(elf::SHN_UNDEF),
+    };
+    let make = |name: u32, value: SymbolValue, size: u32, info: u8, shndx: u16| Sym32 {
+        st_name: u32(name),
+        st_value: u32(value.address | value.thumb as u32),
+        st_size: u32(size),
+        st_info: info,
+        st_other: 0,
+        st_shndx: u16(shndx),
+    };
+
+    let mut symbols = vec![Sym32 {
+        st_name: u32(0),
+        st_value: u32(0),
+        st_size: u32(0),
+        st_info: 0,
+        st_other: 0,
+        st_shndx: u16(0),
+    }];
+
+    for (object_index, object) in linker.objects.iter().enumerate() {
+        let id = ObjectId(object_index);
+        for (index, symbol) in object.symbols.iter().enumerate() {
+            if !symbol.is_local()
+                || symbol.name.is_empty()
+                || symbol.kind == elf::STT_SECTION
+                || symbol.kind == elf::STT_FILE
+            {
+                continue;
+            }
+            let (value, shndx) = match (
+                linker.symbol_value(layout, id, index),
+                output_index(id, symbol.section),
+            ) {
+                (Some(value), Some(shndx)) => (value, shndx),
+                _ => continue,
+            };
+            let name = strings.add(&symbol.name);
+            let info = (elf::STB_LOCAL << 4) | symbol.kind;
+            symbols.push(make(name, value, symbol.size, info, shndx));
+        }
+    }
+
+    let first_global = symbols.len() as u32;
+    let mut globals: Vec<_> = linker.symbols.iter().collect();
+    globals.sort_by_key(|(name, _)| *name);
+    for (name, global) in globals {
+        let (object, index) = match global.definition {
+            Some(def) => (def.object, def.index),
+            None => match global.referenced_by {
+                Some(object) => (object, usize::MAX),
+                None => continue,
+            },
+        };
+        let (value, size, info, shndx) = if index == usize::MAX {
+            let bind = if global.weak_reference {
+                elf::STB_WEAK
+            } else {
+                elf::STB_GLOBAL
+            };
+            let value = SymbolValue {
+                address: 0,
+                thumb: false,
+                absolute: true,
+            };
+            (value, 0, (bind << 4) | elf::STT_NOTYPE, elf::SHN_UNDEF)
+        } else {
+            let symbol = &linker.objects[object.0].symbols[index];
+            let (value, shndx) = match (
+                linker.symbol_value(layout, object, index),
+                output_index(object, symbol.section),
+            ) {
+                (Some(value), Some(shndx)) => (value, shndx),
+                _ => continue,
+            };
+            (
+                value,
+                symbol.size,
+                (symbol.binding << 4) | symbol.kind,
+                shndx,
+            )
+        };
+        let name = strings.add(name);
+        symbols.push(make(name, value, size, info, shndx));
+    }
+
+    (symbols, first_global)
+}
+
+/// Path of the debug companion of an output file.
+pub fn debug_file_path(output: &Path) -> PathBuf {
+    output.with_extension("debug.elf")
+}
+
+/// Serialize the laid out and relocated image into the output file and its debug companion.
+pub fn write(
+    linker: &Linker<'_>,
+    layout: &Layout,
+    entry: u32,
+    dynamic_relocations: &[DynamicRelocation],
+) -> Result<(), LinkError> {
+    let path = &linker.input.output_file;
+    let debug_path = debug_file_path(path);
+    let debug = image(linker, layout, entry, dynamic_relocations, None);
+    let debug_link = debug_link(&debug_path, &debug);
+    let stripped = image(linker, layout, entry, dynamic_relocations, Some(debug_link));
+
+    for (path, data) in [(&debug_path, debug), (path, stripped)] {
+        fs::write(path, data).map_err(|source| LinkError::Write {
+            path: path.clone(),
+            source,
+        })?;
+    }
+    Ok(())
+}
+
+/// Contents of `.gnu_debuglink`, the file name of the companion followed by its CRC.
+fn debug_link(debug_path: &Path, debug: &[u8]) -> Vec<u8> {
+    let name = debug_path.file_name().unwrap_or_default();
+    let mut data = name.to_string_lossy().into_owned().into_bytes();
+    data.push(0);
+    data.resize(align_up(data.len() as u32, 4) as usize, 0);
+    data.extend_from_slice(&debug_link_crc(debug).to_le_bytes());
+    data
+}
+
+/// CRC-32 of a debug companion as `.gnu_debuglink` holds it, the one of zlib.
+pub fn debug_link_crc(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+/// Serialize the image, either the debug companion with everything, or the stripped output
+/// with the contents of its `.gnu_debuglink`.
+fn image(
+    linker: &Linker<'_>,
+    layout: &Layout,
+    entry: u32,
+    dynamic_relocations: &[DynamicRelocation],
+    debug_link: Option<Vec<u8>>,
+) -> Vec<u8> {
+    let e_type = match linker.input.output_options {
+        OutputOptions::Executable { pic: false } => ET_SCE_EXEC,
+        OutputOptions::Executable { pic: true } | OutputOptions::Shared { .. } => ET_SCE_RELEXEC,
+    };
+
+    let stripped = debug_link.is_some();
+    let mut section_names = StringTable::new();
+    // layout indices of the written sections, the debug information is not loaded
+    let sections: Vec<_> = (0..layout.sections.len())
+        .filter(|&index| !stripped || layout.sections[index].flags & elf::SHF_ALLOC != 0)
+        .collect();
+
+    // header indices of the sections following the laid out ones
+    let first_extra = sections.len() as u32 + 1;
+    let sce_relocations = super::sce_relocations(layout, dynamic_relocations);
+    // relocations get a `PT_SCE_RELA` segment following the loaded ones
+    let has_relocations = !sce_relocations.is_empty();
+    let symtab_index = first_extra + has_relocations as u32;
+    let mut extra = Vec::new();
+    if has_relocations {
+        extra.push(ExtraSection {
+            name: ".sce.rel",
+            sh_type: SHT_SCE_RELA,
+            link: 0,
+            info: 0,
+            align: 16,
+            entsize: 0,
+            data: sce_relocations,
+        });
+    }
+    match debug_link {
+        Some(data) => extra.push(ExtraSection {
+            name: ".gnu_debuglink",
+            sh_type: elf::SHT_PROGBITS,
+            link: 0,
+            info: 0,
+            align: 4,
+            entsize: 0,
+            data,
+        }),
+        None => {
+            let mut strings = StringTable::new();
+            let (symbols, first_global) = symbol_table(linker, layout, &mut strings);
+            extra.push(ExtraSection {
+                name: ".symtab",
+                sh_type: elf::SHT_SYMTAB,
+                link: symtab_index + 1,
+                info: first_global,
+                align: 4,
+                entsize: mem::size_of::<Sym32>() as u32,
+                data: bytes_of_vec(&symbols),
+            });
+            extra.push(ExtraSection {
+                name: ".strtab",
+                sh_type: elf::SHT_STRTAB,
+                link: 0,
+                info: 0,
+                align: 1,
+                entsize: 0,
+                data: strings.data,
+            });
+        }
+    }
+    let shstrtab_index = first_extra + extra.len() as u32;
+    let shstrtab_name = section_names.add(".shstrtab");
+    let extra_names: Vec<_> = extra.iter().map(|s| section_names.add(s.name)).collect();
+    let layout_names: Vec<_> = sections
+        .iter()
+        .map(|&index| section_names.add(&layout.sections[index].name))
+        .collect();
+
+    let ehdr_size = mem::size_of::<elf::FileHeader32<VitaEndian>>() as u32;
+    let phdr_size = mem::size_of::<elf::ProgramHeader32<VitaEndian>>() as u32;
+    let shdr_size = mem::size_of::<elf::SectionHeader32<VitaEndian>>() as u32;
+
+    // the search table for unwinders gets `PT_GNU_EH_FRAME` in the debug companion only, as
+    // the loader of SCE images does not know the segment type, see `EH_FRAME_HDR_SYMBOL`
+    let eh_frame_hdr = layout
+        .find_section(EH_FRAME_HDR_SECTION)
+        .filter(|_| !stripped);
+
+    // assign file offsets
+    let phnum =
+        layout.segments.len() as u32 + has_relocations as u32 + eh_frame_hdr.is_some() as u32;
+    let mut offset = ehdr_size + phdr_size * phnum;
+    let mut section_offsets = vec![0; layout.sections.len()];
+    let mut segment_offsets = Vec::with_capacity(layout.segments.len());
+    for segment in &layout.segments {
+        let segment_offset = align_up(offset, segment.align);
+        for index in segment.sections.clone() {
+            section_offsets[index] =
+                segment_offset + (layout.sections[index].address - segment.address);
+        }
+        segment_offsets.push(segment_offset);
+        offset = segment_offset + segment.file_size;
+    }
+    for &index in &sections {
+        let section = &layout.sections[index];
+        if section.flags & elf::SHF_ALLOC == 0 {
+            offset = align_up(offset, section.align);
+            section_offsets[index] = offset;
+            offset += section.data.len() as u32;
+        }
+    }
+    let mut extra_offsets = Vec::with_capacity(extra.len());
+    for section in &extra {
+        offset = align_up(offset, section.align);
+        extra_offsets.push(offset);
+        offset += section.data.len() as u32;
+    }
+    let shstrtab_offset = offset;
+    offset += section_names.data.len() as u32;
+    let shoff = align_up(offset, 4);
+    let shnum = shstrtab_index + 1;
+    let file_size = shoff + shdr_size * shnum;
+
+    let mut out = vec![0; file_size as usize];
+    let mut put = |at: u32, bytes: &[u8]| {
+        out[at as usize..at as usize + bytes.len()].copy_from_slice(bytes);
+    };
+
+    let header = elf::FileHeader32 {
+        e_ident: elf::Ident {
+            magic: elf::ELFMAG,
+            class: elf::ELFCLASS32,
+            data: elf::ELFDATA2LSB,
+            version: elf::EV_CURRENT,
+            os_abi: elf::ELFOSABI_SYSV,
+            abi_version: 0,
+            padding: [0; 7],
+        },
+        e_type: u16(e_type),
+        e_machine: u16(elf::EM_ARM),
+        e_version: u32(elf::EV_CURRENT.into()),
+        e_entry: u32(entry),
+        e_phoff: u32(ehdr_size),
+        e_shoff: u32(shoff),
+        e_flags: u32(elf::EF_ARM_EABI_VER5 | elf::EF_ARM_ABI_FLOAT_HARD),
+        e_ehsize: u16(ehdr_size as u16),
+        e_phentsize: u16(phdr_size as u16),
+        e_phnum: u16(phnum as u16),
+        e_shentsize: u16(shdr_size as u16),
+        e_shnum: u16(shnum as u16),
+        e_shstrndx: u16(shstrtab_index as u16),
+    };
+    put(0, bytes_of(&header));
+
+    for (index, segment) in layout.segments.iter().enumerate() {
+        let phdr = elf::ProgramHeader32 {
+            p_type: u32(elf::PT_LOAD),
+            p_offset: u32(segment_offsets[index]),
+            p_vaddr: u32(segment.address),
+            p_paddr: u32(segment.address),
+            p_filesz: u32(segment.file_size),
+            p_memsz: u32(segment.mem_size),
+            p_flags: u32(segment.flags),
+            p_align: u32(segment.align),
+        };
+        put(ehdr_size + phdr_size * index as u32, bytes_of(&phdr));
+    }
+    if has_relocations {
+        let phdr = elf::ProgramHeader32 {
+            p_type: u32(PT_SCE_RELA),
+            p_offset: u32(extra_offsets[0]),
+            p_vaddr: u32(0),
+            p_paddr: u32(0),
+            p_filesz: u32(extra[0].data.len() as u32),
+            p_memsz: u32(0),
+            p_flags: u32(0),
+            p_align: u32(extra[0].align),
+        };
+        put(
+            ehdr_size + phdr_size * layout.segments.len() as u32,
+            bytes_of(&phdr),
+        );
+    }
+
+    if let Some(index) = eh_frame_hdr {
+        let section = &layout.sections[index];
+        let phdr = elf::ProgramHeader32 {
+            p_type: u32(elf::PT_GNU_EH_FRAME),
+            p_offset: u32(section_offsets[index]),
+            p_vaddr: u32(section.address),
+            p_paddr: u32(section.address),
+            p_filesz: u32(section.size),
+            p_memsz: u32(section.size),
+            p_flags: u32(elf::PF_R),
+            p_align: u32(section.align),
+        };
+        put(ehdr_size + phdr_size * (phnum - 1), bytes_of(&phdr));
+    }
+
+    let mut headers = vec![elf::SectionHeader32 {
+        sh_name: u32(0),
+        sh_type: u32(elf::SHT_NULL),
+        sh_flags: u32(0),
+        sh_addr: u32(0),
+        sh_offset: u32(0),
+        sh_size: u32(0),
+        sh_link: u32(0),
+        sh_info: u32(0),
+        sh_addralign: u32(0),
+        sh_entsize: u32(0),
+    }];
+    for (&index, &name) in sections.iter().zip(&layout_names) {
+        let section = &layout.sections[index];
+        put(section_offsets[index], &section.data);
+        headers.push(elf::SectionHeader32 {
+            sh_name: u32(name),
+            sh_type: u32(section.sh_type),
+            sh_flags: u32(section.flags),
+            sh_addr: u32(section.address),
+            sh_offset: u32(section_offsets[index]),
+            sh_size: u32(section.size),
+            sh_link: u32(0),
+            sh_info: u32(0),
+            sh_addralign: u32(section.align),
+            sh_entsize: u32(0),
+        });
+    }
+    for (index, section) in extra.iter().enumerate() {
+        put(extra_offsets[index], &section.data);
+        headers.push(elf::SectionHeader32 {
+            sh_name: u32(extra_names[index]),
+            sh_type: u32(section.sh_type),
+            sh_flags: u32(0),
+            sh_addr: u32(0),
+            sh_offset: u32(extra_offsets[index]),
+            sh_size: u32(section.data.len() as u32),
+            sh_link: u32(section.link),
+            sh_info: u32(section.info),
+            sh_addralign: u32(section.align),
+            sh_entsize: u32(section.entsize),
+        });
+    }
+    put(shstrtab_offset, &section_names.data);
+    headers.push(elf::SectionHeader32 {
+        sh_name: u32(shstrtab_name),
+        sh_type: u32(elf::SHT_STRTAB),
+        sh_flags: u32(0),
+        sh_addr: u32(0),
+        sh_offset: u32(shstrtab_offset),
+        sh_size: u32(section_names.data.len() as u32),
+        sh_link: u32(0),
+        sh_info: u32(0),
+        sh_addralign: u32(1),
+        sh_entsize: u32(0),
+    });
+    put(shoff, &bytes_of_vec(&headers));
+    out
+}
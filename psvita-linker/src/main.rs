@@ -1,10 +1,22 @@
 use log::debug;
-use psvita_linker::input::Input;
+use psvita_linker::{input::Input, link};
+use std::process;
 
 fn main() {
     pretty_env_logger::init_custom_env("PSVITA_LINKER_LOG");
 
-    let input = Input::from_args();
+    let input = match Input::from_args() {
+        Ok(input) => input,
+        Err(e) => {
+            eprintln!("psvita-linker: error: {}", e);
+            // tells an invalid invocation apart from a failed link
+            process::exit(2);
+        }
+    };
     debug!("Parsed input as: {:#?}", &input);
-    todo!()
+
+    if let Err(e) = link::link(&input) {
+        eprintln!("psvita-linker: error: {}", e);
+        process::exit(1);
+    }
 }
@@ -0,0 +1,127 @@
+use ld_compat_args::ArgsError;
+use psvita_linker::input::{Input, InputError, InputNode, OutputOptions};
+use std::{env, fs};
+
+fn parse(args: &[&str]) -> Result<Input, InputError> {
+    Input::parse(args.iter().map(|&s| s.to_owned()))
+}
+
+#[test]
+fn parses_output_type() {
+    let input = parse(&["main.o", "-pie", "-o", "out.elf"]).unwrap();
+    assert!(matches!(
+        input.output_options,
+        OutputOptions::Executable { pic: true }
+    ));
+    assert_eq!(input.output_file.to_str(), Some("out.elf"));
+
+    let input = parse(&["-shared", "main.o"]).unwrap();
+    assert!(matches!(
+        input.output_options,
+        OutputOptions::Shared {
+            version_script: None
+        }
+    ));
+    assert_eq!(input.output_file.to_str(), Some("a.out"));
+}
+
+#[test]
+fn reports_offending_arguments() {
+    let err = parse(&["main.o", "--frobnicate"]).unwrap_err();
+    assert!(matches!(
+        &err,
+        InputError::Argument {
+            source: ArgsError::UnknownOption { option, .. }
+        } if option == "--frobnicate"
+    ));
+    assert_eq!(err.position(), 2);
+
+    let err = parse(&["main.o", "-o"]).unwrap_err();
+    assert!(matches!(
+        &err,
+        InputError::Argument {
+            source: ArgsError::MissingValue { .. }
+        }
+    ));
+    assert_eq!(err.position(), 2);
+
+    let err = parse(&["-o", "a.elf", "main.o", "--output=b.elf"]).unwrap_err();
+    assert!(matches!(
+        &err,
+        InputError::DuplicateOutput { position: 4, argument } if argument == "--output=b.elf"
+    ));
+
+    let err = parse(&["-shared", "main.o", "-pie"]).unwrap_err();
+    assert!(matches!(
+        &err,
+        InputError::OutputType { position: 3, argument, .. } if argument == "-pie"
+    ));
+
+    let err = parse(&["main.o", "--version-script", "/nonexistent/list"]).unwrap_err();
+    assert!(matches!(
+        err,
+        InputError::ReadVersionScript { position: 2, .. }
+    ));
+    assert!(err.to_string().contains("/nonexistent/list"));
+
+    let script = env::temp_dir().join("psvita-linker-malformed.version-script");
+    fs::write(&script, "{ global: foo; local: *;").unwrap();
+    let err = parse(&["main.o", "--version-script", script.to_str().unwrap()]).unwrap_err();
+    assert!(matches!(
+        &err,
+        InputError::ParseVersionScript { position: 2, path, .. } if *path == script
+    ));
+    assert!(err.to_string().contains(script.to_str().unwrap()));
+}
+
+#[test]
+fn parses_archive_groups() {
+    let input = parse(&["main.o", "-(", "-lc", "--start-group", "-lgcc"]);
+    assert!(matches!(
+        input,
+        Err(InputError::NestedGroup { position: 4, .. })
+    ));
+
+    let input = parse(&["main.o", "--end-group"]);
+    assert!(matches!(
+        input,
+        Err(InputError::UnmatchedEndGroup { position: 2, .. })
+    ));
+
+    let input = parse(&[
+        "main.o",
+        "-(",
+        "-lc",
+        "libgcc.a",
+        "-)",
+        "-lm",
+        "--start-group",
+        "-lx",
+    ]);
+    let shape: Vec<_> = input
+        .unwrap()
+        .inputs
+        .iter()
+        .map(|node| match node {
+            InputNode::File(_) => String::from("file"),
+            InputNode::Library(_) => String::from("library"),
+            InputNode::Group(nodes) => format!("group of {}", nodes.len()),
+        })
+        .collect();
+    assert_eq!(shape, ["file", "group of 2", "library", "group of 1"]);
+}
+
+#[test]
+fn records_as_needed_per_library() {
+    let input = parse(&["--as-needed", "-lc", "--no-as-needed", "-lm", "main.o"]).unwrap();
+    let as_needed: Vec<_> = input
+        .inputs
+        .iter()
+        .map(|node| match node {
+            InputNode::File(file) => file.as_needed,
+            InputNode::Library(library) => library.as_needed,
+            InputNode::Group(_) => unreachable!(),
+        })
+        .collect();
+    assert_eq!(as_needed, [true, false, false]);
+}
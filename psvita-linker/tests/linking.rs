@@ -0,0 +1,2117 @@
+use ld_compat_args::{Library, ZKeyword};
+use object::{
+    elf,
+    read::{Object as _, ObjectSection as _, ObjectSegment as _, ObjectSymbol as _},
+    write::{Object, Relocation, SectionId, StandardSection, Symbol, SymbolId, SymbolSection},
+    Architecture, BinaryFormat, Endianness, RelocationEncoding, RelocationKind, SectionKind,
+    SymbolFlags, SymbolKind, SymbolScope,
+};
+use psvita_linker::{
+    input::{Input, InputFile, InputLibrary, InputNode, OutputOptions},
+    link,
+};
+use std::{
+    convert::TryInto,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+fn new_object() -> Object {
+    Object::new(BinaryFormat::Elf, Architecture::Arm, Endianness::Little)
+}
+
+fn add_symbol(
+    obj: &mut Object,
+    name: &str,
+    section: SectionId,
+    value: u64,
+    kind: SymbolKind,
+) -> SymbolId {
+    obj.add_symbol(Symbol {
+        name: name.as_bytes().to_vec(),
+        value,
+        size: 0,
+        kind,
+        scope: SymbolScope::Linkage,
+        weak: false,
+        section: SymbolSection::Section(section),
+        flags: SymbolFlags::None,
+    })
+}
+
+fn add_undefined(obj: &mut Object, name: &str) -> SymbolId {
+    obj.add_symbol(Symbol {
+        name: name.as_bytes().to_vec(),
+        value: 0,
+        size: 0,
+        kind: SymbolKind::Unknown,
+        scope: SymbolScope::Unknown,
+        weak: false,
+        section: SymbolSection::Undefined,
+        flags: SymbolFlags::None,
+    })
+}
+
+fn add_abs32(obj: &mut Object, section: SectionId, offset: u64, symbol: SymbolId) {
+    obj.add_relocation(
+        section,
+        Relocation {
+            offset,
+            size: 32,
+            kind: RelocationKind::Elf(elf::R_ARM_ABS32),
+            encoding: RelocationEncoding::Generic,
+            symbol,
+            addend: 0,
+        },
+    )
+    .unwrap();
+}
+
+fn add_reloc(obj: &mut Object, section: SectionId, offset: u64, symbol: SymbolId, r_type: u32) {
+    obj.add_relocation(
+        section,
+        Relocation {
+            offset,
+            size: 0,
+            kind: RelocationKind::Elf(r_type),
+            encoding: RelocationEncoding::Generic,
+            symbol,
+            addend: 0,
+        },
+    )
+    .unwrap();
+}
+
+fn temp_dir(test: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("psvita-linker-{}-{}", test, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn link_objects(test: &str, objects: &[Object], output_options: OutputOptions) -> Vec<u8> {
+    let files: Vec<_> = objects
+        .iter()
+        .enumerate()
+        .map(|(i, obj)| (format!("{}.o", i), obj.write().unwrap()))
+        .collect();
+    link_files(test, &files, output_options)
+}
+
+/// Link the files, returns the debug companion for tests to look up symbols in.
+fn link_files(test: &str, files: &[(String, Vec<u8>)], output_options: OutputOptions) -> Vec<u8> {
+    let input = link_input(test, files, output_options);
+    link::link(&input).unwrap();
+    read_debug_output(&input)
+}
+
+/// The output image, as it is shipped.
+fn read_output(input: &Input) -> Vec<u8> {
+    fs::read(&input.output_file).unwrap()
+}
+
+/// Debug companion of the output, which has its layout along with the symbol table.
+fn read_debug_output(input: &Input) -> Vec<u8> {
+    fs::read(link::debug_file_path(&input.output_file)).unwrap()
+}
+
+fn link_input(test: &str, files: &[(String, Vec<u8>)], output_options: OutputOptions) -> Input {
+    let dir = temp_dir(test);
+    let inputs = files
+        .iter()
+        .map(|(name, data)| {
+            let path = dir.join(name);
+            fs::write(&path, data).unwrap();
+            InputNode::File(InputFile {
+                path,
+                gc_sections: false,
+                whole_archive: false,
+                as_needed: false,
+            })
+        })
+        .collect();
+    Input {
+        inputs,
+        library_paths: Vec::new(),
+        output_file: dir.join("out.elf"),
+        output_options,
+        eh_frame_header: false,
+        z_keywords: Vec::new(),
+        map_file: None,
+    }
+}
+
+fn input_file(input: &mut Input, index: usize) -> &mut InputFile {
+    match &mut input.inputs[index] {
+        InputNode::File(file) => file,
+        node => panic!("not a file: {:?}", node),
+    }
+}
+
+/// Build an `ar` archive, with GNU long names or BSD `#1/` names.
+fn write_archive(members: &[(&str, Vec<u8>)], bsd: bool) -> Vec<u8> {
+    fn header(out: &mut Vec<u8>, name: &str, size: usize) {
+        out.extend(
+            format!(
+                "{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n",
+                name, 0, 0, 0, 644, size
+            )
+            .bytes(),
+        );
+    }
+    fn pad(out: &mut Vec<u8>) {
+        if out.len() & 1 != 0 {
+            out.push(b'\n');
+        }
+    }
+
+    let mut out = b"!<arch>\n".to_vec();
+    let mut names = Vec::new();
+    let mut offsets = Vec::new();
+    for (name, _) in members {
+        offsets.push(names.len());
+        names.extend(format!("{}/\n", name).bytes());
+    }
+    if !bsd {
+        header(&mut out, "//", names.len());
+        out.extend(&names);
+        pad(&mut out);
+    }
+    for ((name, data), offset) in members.iter().zip(offsets) {
+        if bsd {
+            header(
+                &mut out,
+                &format!("#1/{}", name.len()),
+                name.len() + data.len(),
+            );
+            out.extend(name.bytes());
+        } else {
+            header(&mut out, &format!("/{}", offset), data.len());
+        }
+        out.extend(data);
+        pad(&mut out);
+    }
+    out
+}
+
+fn symbol_address(file: &object::File<'_>, name: &str) -> u64 {
+    file.symbols()
+        .find(|s| s.name() == Ok(name))
+        .unwrap_or_else(|| panic!("no symbol `{}`", name))
+        .address()
+}
+
+/// SCE relocations of the output as `(symseg, code, datseg, offset, addend)`.
+fn sce_relocations(file: &object::File<'_>) -> Vec<(u8, u8, u8, u32, u32)> {
+    let data = file.section_by_name(".sce.rel").unwrap().data().unwrap();
+    let word = |at: usize| u32::from_le_bytes(data[at..at + 4].try_into().unwrap());
+    let mut relocations = Vec::new();
+    let mut at = 0;
+    while at < data.len() {
+        let info = word(at);
+        let (symseg, code, datseg) = (
+            (info >> 4) as u8 & 0xF,
+            (info >> 8) as u8,
+            (info >> 16) as u8 & 0xF,
+        );
+        if info & 0xF == 1 {
+            let rest = word(at + 4);
+            relocations.push((
+                symseg,
+                code,
+                datseg,
+                info >> 20 | (rest & 0x3FF) << 12,
+                rest >> 10,
+            ));
+            at += 8;
+        } else {
+            relocations.push((symseg, code, datseg, word(at + 8), word(at + 4)));
+            at += 12;
+        }
+    }
+    relocations
+}
+
+/// Address of `module_start`, from the module info found through `e_entry`.
+fn start_entry(file: &object::File<'_>) -> u64 {
+    let entry = file.entry() as u32;
+    assert_eq!(entry >> 30, 0);
+    let base = file.section_by_name(".text").unwrap().address();
+    let start = read_word(file, base as u32 + entry + 0x44);
+    base + start as u64
+}
+
+#[test]
+fn resolves_data_references_across_objects() {
+    let mut main = new_object();
+    let text = main.section_id(StandardSection::Text);
+    main.append_section_data(text, &0xe12f_ff1e_u32.to_le_bytes(), 4);
+    add_symbol(&mut main, "_start", text, 0, SymbolKind::Text);
+    let data = main.section_id(StandardSection::Data);
+    main.append_section_data(data, &[0; 4], 4);
+    let value = add_undefined(&mut main, "value");
+    add_abs32(&mut main, data, 0, value);
+
+    let mut other = new_object();
+    let rodata = other.section_id(StandardSection::ReadOnlyData);
+    other.append_section_data(rodata, &42u32.to_le_bytes(), 4);
+    add_symbol(&mut other, "value", rodata, 0, SymbolKind::Data);
+
+    let out = link_objects(
+        "data-references",
+        &[main, other],
+        OutputOptions::Executable { pic: true },
+    );
+    let file = object::File::parse(&*out).unwrap();
+
+    let value = symbol_address(&file, "value") as u32;
+    assert_eq!(start_entry(&file), symbol_address(&file, "_start"));
+    let data = file.section_by_name(".data").unwrap().data().unwrap();
+    assert_eq!(data, value.to_le_bytes());
+
+    // `value` is in the first segment, the relocated word at the start of the second one
+    let relocations = sce_relocations(&file);
+    let text = file.section_by_name(".text").unwrap().address() as u32;
+    assert_eq!(
+        relocations[0],
+        (0, elf::R_ARM_ABS32 as u8, 1, 0, value - text)
+    );
+}
+
+fn file_name(path: &str) -> &str {
+    Path::new(path).file_name().unwrap().to_str().unwrap()
+}
+
+#[test]
+fn reports_undefined_symbols() {
+    let mut main = new_object();
+    let data = main.section_id(StandardSection::Data);
+    main.append_section_data(data, &[0; 4], 4);
+    let missing = add_undefined(&mut main, "missing");
+    add_abs32(&mut main, data, 0, missing);
+    let text = main.section_id(StandardSection::Text);
+    main.append_section_data(text, &[0; 8], 4);
+    let panic = add_undefined(&mut main, "_ZN4core9panicking5panic17h0123456789abcdefE");
+    add_abs32(&mut main, text, 0, panic);
+    let exit = add_undefined(&mut main, "sceKernelExitProcess");
+    add_abs32(&mut main, text, 4, exit);
+    add_abs32(&mut main, data, 0, exit);
+
+    let dir = temp_dir("undefined");
+    let path = dir.join("main.o");
+    fs::write(&path, main.write().unwrap()).unwrap();
+    let input = Input {
+        inputs: vec![InputNode::File(InputFile {
+            path,
+            gc_sections: false,
+            whole_archive: false,
+            as_needed: false,
+        })],
+        library_paths: Vec::new(),
+        output_file: dir.join("out.elf"),
+        output_options: OutputOptions::Shared {
+            version_script: None,
+        },
+        eh_frame_header: false,
+        z_keywords: Vec::new(),
+        map_file: None,
+    };
+    let err = link::link(&input).unwrap_err();
+    let references = match &err {
+        link::LinkError::UndefinedSymbols(references) => references,
+        err => panic!("unexpected error: {}", err),
+    };
+    let references: Vec<_> = references
+        .iter()
+        .map(|r| {
+            (
+                r.symbol.as_str(),
+                file_name(&r.location.object),
+                r.location.section.as_deref(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        references,
+        [
+            (
+                "_ZN4core9panicking5panic17h0123456789abcdefE",
+                "main.o",
+                Some(".text")
+            ),
+            ("missing", "main.o", Some(".data")),
+            ("sceKernelExitProcess", "main.o", Some(".data")),
+            ("sceKernelExitProcess", "main.o", Some(".text")),
+        ]
+    );
+
+    let message = err.to_string();
+    assert!(message.contains("undefined symbol `core::panicking::panic` referenced in `"));
+    assert!(message.contains("hint: `sceKernelExitProcess`"));
+    assert!(!message.contains("hint: `missing`"));
+}
+
+#[test]
+fn reports_every_duplicate_definition() {
+    let mut first = new_object();
+    let text = first.add_section(Vec::new(), b".text.first".to_vec(), SectionKind::Text);
+    first.append_section_data(text, &[0; 4], 4);
+    add_symbol(
+        &mut first,
+        "_ZN4demo5value17h0123456789abcdefE",
+        text,
+        0,
+        SymbolKind::Text,
+    );
+
+    let mut second = new_object();
+    let data = second.section_id(StandardSection::Data);
+    second.append_section_data(data, &[0; 4], 4);
+    add_symbol(
+        &mut second,
+        "_ZN4demo5value17h0123456789abcdefE",
+        data,
+        0,
+        SymbolKind::Data,
+    );
+    // every duplicate is reported, not only the first one
+    for object in [&mut first, &mut second] {
+        let text = object.section_id(StandardSection::Text);
+        object.append_section_data(text, &[0; 4], 4);
+        add_symbol(object, "other", text, 0, SymbolKind::Text);
+    }
+
+    let input = link_input(
+        "duplicate",
+        &[
+            ("first.o".to_owned(), first.write().unwrap()),
+            ("second.o".to_owned(), second.write().unwrap()),
+        ],
+        OutputOptions::Executable { pic: false },
+    );
+    let err = link::link(&input).unwrap_err();
+    let duplicates = match &err {
+        link::LinkError::DuplicateSymbols(duplicates) => duplicates,
+        err => panic!("unexpected error: {}", err),
+    };
+    let mut symbols: Vec<_> = duplicates.iter().map(|d| d.symbol.as_str()).collect();
+    symbols.sort_unstable();
+    assert_eq!(symbols, ["_ZN4demo5value17h0123456789abcdefE", "other"]);
+    let value = duplicates.iter().find(|d| d.symbol != "other").unwrap();
+    assert_eq!(
+        (
+            file_name(&value.first.object),
+            value.first.section.as_deref()
+        ),
+        ("first.o", Some(".text.first"))
+    );
+    assert_eq!(
+        (
+            file_name(&value.second.object),
+            value.second.section.as_deref()
+        ),
+        ("second.o", Some(".data"))
+    );
+    let message = err.to_string();
+    assert!(message.contains("duplicate symbol `demo::value`"));
+    assert!(message.contains("duplicate symbol `other`"));
+}
+
+fn archive_link(test: &str, bsd: bool) {
+    let mut main = new_object();
+    let text = main.section_id(StandardSection::Text);
+    main.append_section_data(text, &[0; 4], 4);
+    add_symbol(&mut main, "_start", text, 0, SymbolKind::Text);
+    let data = main.section_id(StandardSection::Data);
+    main.append_section_data(data, &[0; 4], 4);
+    let helper = add_undefined(&mut main, "helper");
+    add_abs32(&mut main, data, 0, helper);
+
+    let mut used = new_object();
+    let rodata = used.section_id(StandardSection::ReadOnlyData);
+    used.append_section_data(rodata, &[1; 4], 4);
+    add_symbol(&mut used, "helper", rodata, 0, SymbolKind::Data);
+
+    // loading this member would fail the link with an undefined symbol
+    let mut unused = new_object();
+    let data = unused.section_id(StandardSection::Data);
+    unused.append_section_data(data, &[0; 4], 4);
+    add_symbol(&mut unused, "unused", data, 0, SymbolKind::Data);
+    let missing = add_undefined(&mut unused, "missing");
+    add_abs32(&mut unused, data, 0, missing);
+
+    let archive = write_archive(
+        &[
+            ("lib.rmeta", b"rust metadata".to_vec()),
+            ("unused-member-with-a-long-name.o", unused.write().unwrap()),
+            ("used-member-with-a-long-name.o", used.write().unwrap()),
+        ],
+        bsd,
+    );
+    let out = link_files(
+        test,
+        &[
+            ("main.o".to_owned(), main.write().unwrap()),
+            ("libtest.rlib".to_owned(), archive),
+        ],
+        OutputOptions::Executable { pic: false },
+    );
+    let file = object::File::parse(&*out).unwrap();
+
+    let helper = symbol_address(&file, "helper") as u32;
+    let data = file.section_by_name(".data").unwrap().data().unwrap();
+    assert_eq!(data, helper.to_le_bytes());
+    assert!(file.symbols().all(|s| s.name() != Ok("unused")));
+}
+
+#[test]
+fn loads_needed_gnu_archive_members() {
+    archive_link("gnu-archive", false);
+}
+
+#[test]
+fn loads_needed_bsd_archive_members() {
+    archive_link("bsd-archive", true);
+}
+
+#[test]
+fn loads_every_member_of_whole_archives() {
+    let mut main = new_object();
+    let text = main.section_id(StandardSection::Text);
+    main.append_section_data(text, &[0; 4], 4);
+    add_symbol(&mut main, "_start", text, 0, SymbolKind::Text);
+
+    let mut extra = new_object();
+    let data = extra.section_id(StandardSection::Data);
+    extra.append_section_data(data, &[0; 4], 4);
+    add_symbol(&mut extra, "extra", data, 0, SymbolKind::Data);
+
+    let mut duplicate = new_object();
+    let text = duplicate.section_id(StandardSection::Text);
+    duplicate.append_section_data(text, &[0; 8], 4);
+    add_symbol(&mut duplicate, "_start", text, 4, SymbolKind::Text);
+
+    let archive = write_archive(
+        &[
+            ("extra.o", extra.write().unwrap()),
+            ("duplicate.o", duplicate.write().unwrap()),
+        ],
+        false,
+    );
+    let mut input = link_input(
+        "whole-archive",
+        &[
+            ("main.o".to_owned(), main.write().unwrap()),
+            ("libwhole.a".to_owned(), archive),
+        ],
+        OutputOptions::Executable { pic: false },
+    );
+    input_file(&mut input, 1).whole_archive = true;
+    link::link(&input).unwrap();
+    let out = read_debug_output(&input);
+    let file = object::File::parse(&*out).unwrap();
+
+    symbol_address(&file, "extra");
+    let text = file.section_by_name(".text").unwrap().address();
+    assert_eq!(start_entry(&file), text);
+}
+
+#[test]
+fn collects_unreferenced_sections() {
+    let mut main = new_object();
+    let start = main.add_section(Vec::new(), b".text._start".to_vec(), SectionKind::Text);
+    main.append_section_data(start, &[0; 4], 4);
+    add_symbol(&mut main, "_start", start, 0, SymbolKind::Text);
+    let dead = main.add_section(Vec::new(), b".text.dead".to_vec(), SectionKind::Text);
+    main.append_section_data(dead, &[0; 16], 4);
+    add_symbol(&mut main, "dead", dead, 0, SymbolKind::Text);
+    // only the collected section references a symbol which nothing defines
+    let missing = add_undefined(&mut main, "missing");
+    add_abs32(&mut main, dead, 0, missing);
+    let live = main.add_section(Vec::new(), b".data.live".to_vec(), SectionKind::Data);
+    main.append_section_data(live, &[0; 4], 4);
+    add_symbol(&mut main, "live", live, 0, SymbolKind::Data);
+    let live = main.symbol_id(b"live").unwrap();
+    add_abs32(&mut main, start, 0, live);
+    let info = main.add_section(
+        Vec::new(),
+        b".sceModuleInfo.rodata".to_vec(),
+        SectionKind::ReadOnlyData,
+    );
+    main.append_section_data(info, &[0; 8], 4);
+
+    let mut input = link_input(
+        "gc-sections",
+        &[("main.o".to_owned(), main.write().unwrap())],
+        OutputOptions::Executable { pic: false },
+    );
+    input_file(&mut input, 0).gc_sections = true;
+    link::link(&input).unwrap();
+    let out = read_debug_output(&input);
+    let file = object::File::parse(&*out).unwrap();
+
+    assert!(file.symbols().all(|s| s.name() != Ok("dead")));
+    assert_eq!(file.section_by_name(".text").unwrap().size(), 4);
+    symbol_address(&file, "live");
+    assert!(file.section_by_name(".sceModuleInfo.rodata").is_some());
+}
+
+fn input_library(lib: Library, only_static: bool) -> InputLibrary {
+    InputLibrary {
+        lib,
+        only_static,
+        gc_sections: false,
+        whole_archive: false,
+        as_needed: false,
+    }
+}
+
+#[test]
+fn searches_libraries_in_library_paths() {
+    let mut main = new_object();
+    let data = main.section_id(StandardSection::Data);
+    main.append_section_data(data, &[0; 8], 4);
+    let first = add_undefined(&mut main, "first");
+    add_abs32(&mut main, data, 0, first);
+    let second = add_undefined(&mut main, "second");
+    add_abs32(&mut main, data, 4, second);
+
+    let mut lib = new_object();
+    let rodata = lib.section_id(StandardSection::ReadOnlyData);
+    lib.append_section_data(rodata, &[0; 4], 4);
+    add_symbol(&mut lib, "first", rodata, 0, SymbolKind::Data);
+    let mut other = new_object();
+    let rodata = other.section_id(StandardSection::ReadOnlyData);
+    other.append_section_data(rodata, &[0; 4], 4);
+    add_symbol(&mut other, "second", rodata, 0, SymbolKind::Data);
+
+    let mut input = link_input(
+        "library-search",
+        &[("main.o".to_owned(), main.write().unwrap())],
+        OutputOptions::Executable { pic: false },
+    );
+    let dir = input.output_file.parent().unwrap().to_owned();
+    let empty = dir.join("empty");
+    let archives = dir.join("archives");
+    let libs = dir.join("libs");
+    for dir in [&empty, &archives, &libs] {
+        fs::create_dir_all(dir).unwrap();
+    }
+    // within a directory the module comes first, the archive is not even an archive
+    link_demo_module(&libs, "first");
+    fs::write(libs.join("libdemo.a"), b"not an archive").unwrap();
+    // while an archive of an earlier directory wins over a module of a later one
+    fs::write(
+        archives.join("libsecond.a"),
+        write_archive(&[("second.o", other.write().unwrap())], false),
+    )
+    .unwrap();
+    fs::write(libs.join("libsecond.vso"), b"SCE\0").unwrap();
+    fs::write(libs.join("libthird.vso"), b"SCE\0").unwrap();
+    fs::write(
+        libs.join("libthird.a"),
+        write_archive(&[("third.o", lib.write().unwrap())], false),
+    )
+    .unwrap();
+    input.library_paths = vec![empty, archives, libs];
+    input.inputs.extend(vec![
+        InputNode::Library(input_library(Library::Name("demo".to_owned()), false)),
+        InputNode::Library(input_library(Library::Name("second".to_owned()), false)),
+        // `-Bstatic` skips the module
+        InputNode::Library(input_library(Library::Name("third".to_owned()), true)),
+    ]);
+    link::link(&input).unwrap();
+    let out = read_output(&input);
+    let file = object::File::parse(&*out).unwrap();
+    assert!(file.section_by_name(".sceLib.stub").is_some());
+
+    // an encrypted module is found, but cannot be linked against
+    input.library_paths.remove(1);
+    let err = link::link(&input).unwrap_err();
+    assert!(matches!(
+        err,
+        link::LinkError::UnsupportedInput { name, .. } if name.ends_with("libsecond.vso")
+    ));
+}
+
+#[test]
+fn reports_every_path_tried_for_missing_libraries() {
+    let mut input = link_input(
+        "missing-library",
+        &[("main.o".to_owned(), new_object().write().unwrap())],
+        OutputOptions::Executable { pic: false },
+    );
+    let dir = input.output_file.parent().unwrap().to_owned();
+    input.library_paths = vec![dir.join("a"), dir.join("b")];
+    input.inputs.push(InputNode::Library(input_library(
+        Library::Name("missing".to_owned()),
+        false,
+    )));
+
+    let err = link::link(&input).unwrap_err();
+    match err {
+        link::LinkError::LibraryNotFound { library, tried } => {
+            assert_eq!(library, "-lmissing");
+            assert_eq!(
+                tried,
+                [
+                    dir.join("a/libmissing.vso"),
+                    dir.join("a/libmissing.suprx"),
+                    dir.join("a/libmissing.a"),
+                    dir.join("b/libmissing.vso"),
+                    dir.join("b/libmissing.suprx"),
+                    dir.join("b/libmissing.a"),
+                ]
+            );
+        }
+        err => panic!("unexpected error: {}", err),
+    }
+}
+
+fn word_at(file: &object::File<'_>, address: u64) -> u32 {
+    let text = file.section_by_name(".text").unwrap();
+    let offset = (address - text.address()) as usize;
+    let data = text.data().unwrap();
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn sign_extend(value: u32, bits: u32) -> i64 {
+    let shift = 32 - bits;
+    (((value << shift) as i32) >> shift) as i64
+}
+
+#[test]
+fn applies_branch_and_move_relocations() {
+    // _start: bl arm_fn; movw r0, #:lower16:value; movt r0, #:upper16:value
+    let mut main = new_object();
+    let text = main.section_id(StandardSection::Text);
+    let code = [0xEBFF_FFFE_u32, 0xE300_0000, 0xE340_0000];
+    let code: Vec<u8> = code.iter().flat_map(|i| i.to_le_bytes()).collect();
+    main.append_section_data(text, &code, 4);
+    add_symbol(&mut main, "_start", text, 0, SymbolKind::Text);
+    let arm_fn = add_undefined(&mut main, "arm_fn");
+    add_reloc(&mut main, text, 0, arm_fn, elf::R_ARM_CALL);
+    let value = add_undefined(&mut main, "value");
+    add_reloc(&mut main, text, 4, value, elf::R_ARM_MOVW_ABS_NC);
+    add_reloc(&mut main, text, 8, value, elf::R_ARM_MOVT_ABS);
+
+    // arm_fn: bx lr; thumb_fn: bl thumb_callee; movw r0, #:lower16:value; bx lr
+    let mut other = new_object();
+    let text = other.section_id(StandardSection::Text);
+    let code: Vec<u8> = [
+        0xE12F_FF1E_u32.to_le_bytes(),
+        [0xFF, 0xF7, 0xFE, 0xFF],
+        [0x40, 0xF2, 0x00, 0x00],
+        [0x70, 0x47, 0x70, 0x47],
+    ]
+    .concat();
+    other.append_section_data(text, &code, 4);
+    add_symbol(&mut other, "arm_fn", text, 0, SymbolKind::Text);
+    let thumb_fn = add_symbol(&mut other, "thumb_fn", text, 4 | 1, SymbolKind::Text);
+    add_reloc(&mut other, text, 4, thumb_fn, 10 /* R_ARM_THM_CALL */);
+    let value = add_undefined(&mut other, "value");
+    add_reloc(&mut other, text, 8, value, elf::R_ARM_THM_MOVW_ABS_NC);
+    let rodata = other.section_id(StandardSection::ReadOnlyData);
+    other.append_section_data(rodata, &[0; 4], 4);
+    add_symbol(&mut other, "value", rodata, 0, SymbolKind::Data);
+
+    let out = link_objects(
+        "branches",
+        &[main, other],
+        OutputOptions::Executable { pic: false },
+    );
+    let file = object::File::parse(&*out).unwrap();
+    let start = symbol_address(&file, "_start");
+    let arm_fn = symbol_address(&file, "arm_fn");
+    let thumb_fn = symbol_address(&file, "thumb_fn") & !1;
+    let value = symbol_address(&file, "value") as u32;
+
+    let bl = word_at(&file, start);
+    assert_eq!(bl >> 24, 0xEB);
+    let offset = sign_extend((bl & 0x00FF_FFFF) << 2, 26);
+    assert_eq!(start as i64 + 8 + offset, arm_fn as i64);
+
+    let movw = word_at(&file, start + 4);
+    let movt = word_at(&file, start + 8);
+    let imm = |insn: u32| (insn >> 4) & 0xF000 | insn & 0x0FFF;
+    assert_eq!(imm(movt) << 16 | imm(movw), value);
+
+    let bl = word_at(&file, thumb_fn);
+    let (hw1, hw2) = (bl & 0xFFFF, bl >> 16);
+    let s = (hw1 >> 10) & 1;
+    let i1 = !((hw2 >> 13) ^ s) & 1;
+    let i2 = !((hw2 >> 11) ^ s) & 1;
+    let offset = sign_extend(
+        s << 24 | i1 << 23 | i2 << 22 | (hw1 & 0x3FF) << 12 | (hw2 & 0x7FF) << 1,
+        25,
+    );
+    assert_eq!(thumb_fn as i64 + 4 + offset, thumb_fn as i64);
+
+    let movw = word_at(&file, thumb_fn + 4);
+    let (hw1, hw2) = (movw & 0xFFFF, movw >> 16);
+    let imm = (hw1 & 0xF) << 12 | ((hw1 >> 10) & 1) << 11 | ((hw2 >> 12) & 7) << 8 | hw2 & 0xFF;
+    assert_eq!(imm, value & 0xFFFF);
+}
+
+#[test]
+fn replaces_branches_to_undefined_weak_symbols() {
+    // _start: bl weak_fn; bl weak_fn (Thumb)
+    let mut main = new_object();
+    let text = main.section_id(StandardSection::Text);
+    let code = [0xEBFF_FFFE_u32.to_le_bytes(), [0xFF, 0xF7, 0xFE, 0xFF]].concat();
+    main.append_section_data(text, &code, 4);
+    add_symbol(&mut main, "_start", text, 0, SymbolKind::Text);
+    let weak_fn = main.add_symbol(Symbol {
+        name: b"weak_fn".to_vec(),
+        value: 0,
+        size: 0,
+        kind: SymbolKind::Text,
+        scope: SymbolScope::Linkage,
+        weak: true,
+        section: SymbolSection::Undefined,
+        flags: SymbolFlags::None,
+    });
+    add_reloc(&mut main, text, 0, weak_fn, elf::R_ARM_CALL);
+    add_reloc(&mut main, text, 4, weak_fn, 10 /* R_ARM_THM_CALL */);
+
+    let out = link_objects(
+        "undefined-weak",
+        &[main],
+        OutputOptions::Executable { pic: true },
+    );
+    let file = object::File::parse(&*out).unwrap();
+    let start = symbol_address(&file, "_start");
+    assert_eq!(word_at(&file, start), 0xE320_F000);
+    assert_eq!(word_at(&file, start + 4), 0x8000_F3AF);
+    assert_eq!(file.section_by_name(".text").unwrap().size(), 8);
+}
+
+#[test]
+fn rejects_relocations_outside_of_their_section() {
+    let mut main = new_object();
+    let data = main.section_id(StandardSection::Data);
+    main.append_section_data(data, &[0; 4], 4);
+    let value = add_symbol(&mut main, "value", data, 0, SymbolKind::Data);
+    add_abs32(&mut main, data, 2, value);
+
+    let input = link_input(
+        "relocation-bounds",
+        &[("main.o".to_owned(), main.write().unwrap())],
+        OutputOptions::Executable { pic: false },
+    );
+    match link::link(&input).unwrap_err() {
+        link::LinkError::RelocationOutOfBounds {
+            section, offset, ..
+        } => {
+            assert_eq!((section.as_str(), offset), (".data", 2));
+        }
+        err => panic!("unexpected error: {}", err),
+    }
+}
+
+fn far_symbol(obj: &mut Object) -> SymbolId {
+    obj.add_symbol(Symbol {
+        name: b"far".to_vec(),
+        value: 0x1000_0000,
+        size: 0,
+        kind: SymbolKind::Text,
+        scope: SymbolScope::Linkage,
+        weak: false,
+        section: SymbolSection::Absolute,
+        flags: SymbolFlags::None,
+    })
+}
+
+#[test]
+fn reports_relocation_overflow() {
+    let mut main = new_object();
+    let text = main.section_id(StandardSection::Text);
+    main.append_section_data(text, &[0; 4], 4);
+    add_symbol(&mut main, "_start", text, 0, SymbolKind::Text);
+    let far = far_symbol(&mut main);
+    add_reloc(&mut main, text, 0, far, elf::R_ARM_PREL31);
+
+    let input = link_input(
+        "overflow",
+        &[("main.o".to_owned(), main.write().unwrap())],
+        OutputOptions::Executable { pic: false },
+    );
+    let err = link::link(&input).unwrap_err();
+    assert!(
+        matches!(&err, link::LinkError::RelocationOverflow { symbol, section, .. } if symbol == "far" && section == ".text"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn switches_modes_and_extends_branches_with_veneers() {
+    // _start: bl thumb_fn; b thumb_fn; b far
+    let mut main = new_object();
+    let text = main.section_id(StandardSection::Text);
+    let code = [0xEBFF_FFFE_u32, 0xEAFF_FFFE, 0xEAFF_FFFE];
+    let code: Vec<u8> = code.iter().flat_map(|i| i.to_le_bytes()).collect();
+    main.append_section_data(text, &code, 4);
+    add_symbol(&mut main, "_start", text, 0, SymbolKind::Text);
+    let thumb_fn = add_undefined(&mut main, "thumb_fn");
+    add_reloc(&mut main, text, 0, thumb_fn, elf::R_ARM_CALL);
+    add_reloc(&mut main, text, 4, thumb_fn, elf::R_ARM_JUMP24);
+    let far = far_symbol(&mut main);
+    add_reloc(&mut main, text, 8, far, elf::R_ARM_JUMP24);
+
+    // thumb_fn: bl _start
+    let mut other = new_object();
+    let text = other.section_id(StandardSection::Text);
+    other.append_section_data(text, &[0xFF, 0xF7, 0xFE, 0xFF], 4);
+    add_symbol(&mut other, "thumb_fn", text, 1, SymbolKind::Text);
+    let start = add_undefined(&mut other, "_start");
+    add_reloc(&mut other, text, 0, start, 10 /* R_ARM_THM_CALL */);
+
+    let out = link_objects(
+        "veneers",
+        &[main, other],
+        OutputOptions::Executable { pic: false },
+    );
+    let file = object::File::parse(&*out).unwrap();
+    let start = symbol_address(&file, "_start");
+    let thumb_fn = symbol_address(&file, "thumb_fn") & !1;
+    let arm_offset = |insn: u32| sign_extend((insn & 0x00FF_FFFF) << 2, 26);
+
+    // `BL` becomes `BLX` with the H bit
+    let blx = word_at(&file, start);
+    assert_eq!(blx >> 25, 0x7D);
+    let h = (blx >> 23) & 2;
+    assert_eq!(
+        start as i64 + 8 + arm_offset(blx) + h as i64,
+        thumb_fn as i64
+    );
+
+    // `B` to Thumb code goes through a veneer
+    let b = word_at(&file, start + 4);
+    let veneer = (start as i64 + 12 + arm_offset(b)) as u64;
+    assert_eq!(veneer, symbol_address(&file, "__thumb_fn_veneer"));
+    let literal = word_at(&file, veneer + 12);
+    assert_eq!(
+        (veneer as u32 + 12).wrapping_add(literal),
+        thumb_fn as u32 | 1
+    );
+
+    // and so does `B` out of range, to an absolute destination
+    let b = word_at(&file, start + 8);
+    let veneer = (start as i64 + 16 + arm_offset(b)) as u64;
+    assert_eq!(veneer, symbol_address(&file, "__far_veneer"));
+    assert_eq!(word_at(&file, veneer + 4), 0x1000_0000);
+
+    // Thumb `BL` to ARM code becomes `BLX`
+    let blx = word_at(&file, thumb_fn);
+    let (hw1, hw2) = (blx & 0xFFFF, blx >> 16);
+    assert_eq!(hw2 & 0x1000, 0);
+    let s = (hw1 >> 10) & 1;
+    let i1 = !((hw2 >> 13) ^ s) & 1;
+    let i2 = !((hw2 >> 11) ^ s) & 1;
+    let offset = sign_extend(
+        s << 24 | i1 << 23 | i2 << 22 | (hw1 & 0x3FF) << 12 | (hw2 & 0x7FF) << 1,
+        25,
+    );
+    assert_eq!(((thumb_fn + 4) & !3) as i64 + offset, start as i64);
+}
+
+/// Word at `address` of whichever section contains it.
+fn read_word(file: &object::File<'_>, address: u32) -> u32 {
+    let address = address as u64;
+    let section = file
+        .sections()
+        .find(|s| (s.address()..s.address() + s.size()).contains(&address))
+        .unwrap_or_else(|| panic!("no section at {:#x}", address));
+    let offset = (address - section.address()) as usize;
+    let data = section.data().unwrap();
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+#[test]
+fn writes_module_info_and_main_export() {
+    let mut main = new_object();
+    let text = main.section_id(StandardSection::Text);
+    // bx lr, in Thumb
+    main.append_section_data(text, &[0x70, 0x47, 0x70, 0x47], 4);
+    add_symbol(&mut main, "module_start", text, 1, SymbolKind::Text);
+    add_symbol(&mut main, "module_stop", text, 3, SymbolKind::Text);
+
+    let out = link_objects(
+        "module-info",
+        &[main],
+        OutputOptions::Shared {
+            version_script: None,
+        },
+    );
+    assert_eq!(out[16..18], 0xFE04u16.to_le_bytes());
+    let file = object::File::parse(&*out).unwrap();
+    let base = file.section_by_name(".text").unwrap().address() as u32;
+
+    let info = file.section_by_name(".sceModuleInfo.rodata").unwrap();
+    assert_eq!(file.entry(), info.address() - base as u64);
+    let info = info.data().unwrap();
+    assert_eq!(info.len(), 0x5C);
+    assert_eq!(&info[2..4], [1, 1]);
+    assert_eq!(&info[4..8], b"out\0");
+    assert_eq!(info[0x1F], 6);
+    let field = |offset: usize| u32::from_le_bytes(info[offset..offset + 4].try_into().unwrap());
+    let module_start = symbol_address(&file, "module_start") as u32;
+    let module_stop = symbol_address(&file, "module_stop") as u32;
+    assert_eq!(field(0x44), module_start - base);
+    assert_eq!(field(0x48), module_stop - base);
+
+    let exports = file.section_by_name(".sceLib.ent").unwrap();
+    let exports_top = exports.address() as u32 - base;
+    assert_eq!(field(0x24), exports_top);
+    assert_eq!(field(0x28), exports_top + 0x20);
+    // no imports yet, so an empty range right after the exports
+    assert_eq!(field(0x2C), exports_top + 0x20);
+    assert_eq!(field(0x30), exports_top + 0x20);
+
+    let export = exports.data().unwrap();
+    let export_field =
+        |offset: usize| u32::from_le_bytes(export[offset..offset + 4].try_into().unwrap());
+    assert_eq!(export[0], 0x20);
+    assert_eq!(export_field(4), 0x8000 | 2 << 16);
+    assert_eq!(export_field(8), 1);
+    let nid_table = export_field(0x18);
+    let entry_table = export_field(0x1C);
+    let nids: Vec<_> = (0..3)
+        .map(|i| read_word(&file, nid_table + i * 4))
+        .collect();
+    assert_eq!(nids, [0x935CD196, 0x79F8E492, 0x6C2224BA]);
+    let module_info = file
+        .section_by_name(".sceModuleInfo.rodata")
+        .unwrap()
+        .address() as u32;
+    let entries: Vec<_> = (0..3)
+        .map(|i| read_word(&file, entry_table + i * 4))
+        .collect();
+    assert_eq!(entries, [module_start, module_stop, module_info]);
+}
+
+/// Object with vitasdk style stubs, as pairs of a symbol and a NID, in one library.
+fn stub_object(
+    library: &str,
+    library_nid: u32,
+    functions: &[(&str, u32)],
+    variables: &[(&str, u32)],
+) -> Object {
+    let mut obj = new_object();
+    for (prefix, stubs, kind, section_kind) in [
+        (
+            ".vitalink.fstubs",
+            functions,
+            SymbolKind::Text,
+            SectionKind::Text,
+        ),
+        (
+            ".vitalink.vstubs",
+            variables,
+            SymbolKind::Data,
+            SectionKind::Data,
+        ),
+    ] {
+        if stubs.is_empty() {
+            continue;
+        }
+        let name = format!("{}.{}", prefix, library).into_bytes();
+        let section = obj.add_section(Vec::new(), name, section_kind);
+        for (symbol, nid) in stubs {
+            let entry: Vec<u8> = [0, library_nid, *nid, 0]
+                .iter()
+                .flat_map(|w: &u32| w.to_le_bytes())
+                .collect();
+            let offset = obj.append_section_data(section, &entry, 16);
+            add_symbol(&mut obj, symbol, section, offset, kind);
+        }
+    }
+    obj
+}
+
+#[test]
+fn generates_import_tables_from_stubs() {
+    let mut main = new_object();
+    let text = main.section_id(StandardSection::Text);
+    main.append_section_data(text, &0xe12f_ff1e_u32.to_le_bytes(), 4);
+    add_symbol(&mut main, "_start", text, 0, SymbolKind::Text);
+    let data = main.section_id(StandardSection::Data);
+    main.append_section_data(data, &[0; 12], 4);
+    for (offset, name) in [
+        "sceKernelExitProcess",
+        "sceKernelGetThreadId",
+        "sceKernelStackChkGuard",
+    ]
+    .iter()
+    .enumerate()
+    {
+        let symbol = add_undefined(&mut main, name);
+        add_abs32(&mut main, data, offset as u64 * 4, symbol);
+    }
+    let debug_info = main.add_section(Vec::new(), b".debug_info".to_vec(), SectionKind::Debug);
+    main.append_section_data(debug_info, &[0; 4], 1);
+    let guard = add_undefined(&mut main, "sceKernelStackChkGuard");
+    add_abs32(&mut main, debug_info, 0, guard);
+    let stubs = stub_object(
+        "SceLibKernel",
+        0xCAE9ACE6,
+        &[
+            ("sceKernelExitProcess", 0x7595D9AA),
+            ("sceKernelGetThreadId", 0x0FB972F9),
+        ],
+        &[("sceKernelStackChkGuard", 0x4458BCF3)],
+    );
+
+    let out = link_objects(
+        "import-tables",
+        &[main, stubs],
+        OutputOptions::Executable { pic: false },
+    );
+    let file = object::File::parse(&*out).unwrap();
+
+    let imports = file.section_by_name(".sceLib.stub").unwrap();
+    let import = imports.data().unwrap();
+    assert_eq!(import.len(), 0x34);
+    let half = |offset: usize| u16::from_le_bytes(import[offset..offset + 2].try_into().unwrap());
+    let field = |offset: usize| u32::from_le_bytes(import[offset..offset + 4].try_into().unwrap());
+    assert_eq!(half(0), 0x34);
+    assert_eq!(half(2), 1);
+    assert_eq!((half(6), half(8), half(10)), (2, 1, 0));
+    assert_eq!(field(0x10), 0xCAE9ACE6);
+
+    let name = file.section_by_name(".sceImport.rodata").unwrap();
+    let offset = (field(0x14) as u64 - name.address()) as usize;
+    assert!(name.data().unwrap()[offset..].starts_with(b"SceLibKernel\0"));
+
+    // NIDs are sorted, with the entries in the same order
+    let function_nids: Vec<_> = (0..2)
+        .map(|i| read_word(&file, field(0x1C) + i * 4))
+        .collect();
+    assert_eq!(function_nids, [0x0FB972F9, 0x7595D9AA]);
+    let function_entries: Vec<_> = (0..2)
+        .map(|i| read_word(&file, field(0x20) + i * 4))
+        .collect();
+    assert_eq!(
+        function_entries,
+        [
+            symbol_address(&file, "sceKernelGetThreadId") as u32,
+            symbol_address(&file, "sceKernelExitProcess") as u32,
+        ]
+    );
+    assert_eq!(read_word(&file, field(0x24)), 0x4458BCF3);
+
+    // the variable entry points at the relocations of its references, in the SCE format
+    let list = read_word(&file, field(0x28));
+    assert_eq!(read_word(&file, list), 12);
+    let relocations = read_word(&file, list + 4) as u64;
+    let table = file.section_by_name(".sceVRel.rodata").unwrap();
+    let at = (relocations - table.address()) as usize;
+    let data = file.segments().nth(1).unwrap().address() as u32;
+    let place = file.section_by_name(".data").unwrap().address() as u32 + 8 - data;
+    let mut expected = vec![0x00, elf::R_ARM_ABS32 as u8, 0x01, 0x00, 0, 0, 0, 0];
+    expected.extend_from_slice(&place.to_le_bytes());
+    assert_eq!(table.data().unwrap()[at..at + 12], expected);
+    // only allocated sections are patched by the loader
+    let debug = file.section_by_name(".debug_info").unwrap().data().unwrap();
+    assert_eq!(debug, 0u32.to_le_bytes());
+    // the places of the references are not global symbols
+    assert!(file
+        .symbols()
+        .filter(|s| s.is_global())
+        .all(|s| !s.name().unwrap().contains("ref0")));
+    assert!(file
+        .sections()
+        .all(|s| !s.name().unwrap().starts_with(".vitalink.vstubs")));
+
+    let info = file.section_by_name(".sceModuleInfo.rodata").unwrap();
+    let info = info.data().unwrap();
+    let base = file.section_by_name(".text").unwrap().address() as u32;
+    let imports_top = u32::from_le_bytes(info[0x2C..0x30].try_into().unwrap());
+    assert_eq!(imports_top, imports.address() as u32 - base);
+}
+
+#[test]
+fn exports_version_script_globals_from_shared_outputs() {
+    let mut lib = new_object();
+    let text = lib.section_id(StandardSection::Text);
+    lib.append_section_data(text, &[0x70, 0x47, 0x70, 0x47], 4);
+    let foo = add_symbol(&mut lib, "foo", text, 1, SymbolKind::Text);
+    let hidden = add_symbol(&mut lib, "hidden", text, 3, SymbolKind::Text);
+    let data = lib.section_id(StandardSection::Data);
+    lib.append_section_data(data, &[0; 4], 4);
+    let counter = add_symbol(&mut lib, "counter", data, 0, SymbolKind::Data);
+    for symbol in [foo, hidden, counter] {
+        lib.symbol_mut(symbol).scope = SymbolScope::Dynamic;
+    }
+
+    let script = ld_version_script::TrivialVersionScript::pretty_parse(
+        "{ global: foo; counter; local: *; };",
+    );
+    let input = link_input(
+        "shared-exports",
+        &[(String::from("lib.o"), lib.write().unwrap())],
+        OutputOptions::Shared {
+            version_script: Some(script),
+        },
+    );
+    link::link(&input).unwrap();
+    let out = read_debug_output(&input);
+    let file = object::File::parse(&*out).unwrap();
+
+    let exports = file.section_by_name(".sceLib.ent").unwrap();
+    let exports = exports.data().unwrap();
+    assert_eq!(exports.len(), 0x40);
+    let export = &exports[0x20..];
+    let half = |offset: usize| u16::from_le_bytes(export[offset..offset + 2].try_into().unwrap());
+    let field = |offset: usize| u32::from_le_bytes(export[offset..offset + 4].try_into().unwrap());
+    assert_eq!(half(2), 1);
+    // AUTO_EXPORT, without MAIN_EXPORT
+    assert_eq!(half(4), 0x0001);
+    assert_eq!((half(6), half(8), half(10)), (1, 1, 0));
+    assert_eq!(half(12), 0);
+    assert_eq!(field(0x10), psvita_sce_types::nid::Nid::generate(b"out").0);
+
+    let nids: Vec<_> = (0..2)
+        .map(|i| read_word(&file, field(0x18) + i * 4))
+        .collect();
+    assert_eq!(
+        nids,
+        [
+            psvita_sce_types::nid::Nid::generate(b"foo").0,
+            psvita_sce_types::nid::Nid::generate(b"counter").0,
+        ]
+    );
+    let entries: Vec<_> = (0..2)
+        .map(|i| read_word(&file, field(0x1C) + i * 4))
+        .collect();
+    assert_eq!(
+        entries,
+        [
+            symbol_address(&file, "foo") as u32,
+            symbol_address(&file, "counter") as u32,
+        ]
+    );
+    let name = field(0x14) as u64;
+    let rodata = file.section_by_name(".sceExport.rodata").unwrap();
+    let offset = (name - rodata.address()) as usize;
+    assert!(rodata.data().unwrap()[offset..].starts_with(b"out\0"));
+}
+
+#[test]
+fn encodes_short_and_long_sce_relocations() {
+    let mut main = new_object();
+    let text = main.section_id(StandardSection::Text);
+    main.append_section_data(text, &0xe12f_ff1e_u32.to_le_bytes(), 4);
+    add_symbol(&mut main, "_start", text, 0, SymbolKind::Text);
+    let rodata = main.section_id(StandardSection::ReadOnlyData);
+    main.append_section_data(rodata, &[0; 0x1000], 4);
+    let near = add_symbol(&mut main, "near", rodata, 0xFFC, SymbolKind::Data);
+    let bss = main.section_id(StandardSection::UninitializedData);
+    main.append_section_bss(bss, 0x40_1000, 4);
+    let far = add_symbol(&mut main, "far", bss, 0x40_0000, SymbolKind::Data);
+    let data = main.section_id(StandardSection::Data);
+    main.append_section_data(data, &[0; 8], 4);
+    add_abs32(&mut main, data, 0, near);
+    add_abs32(&mut main, data, 4, far);
+
+    let out = link_objects(
+        "sce-relocations",
+        &[main],
+        OutputOptions::Executable { pic: true },
+    );
+    let file = object::File::parse(&*out).unwrap();
+    let text = file.segments().next().unwrap().address() as u32;
+    let data = file.segments().nth(1).unwrap().address() as u32;
+    let near = symbol_address(&file, "near") as u32 - text;
+    let far = symbol_address(&file, "far") as u32 - data;
+
+    let relocations = sce_relocations(&file);
+    assert_eq!(relocations[0], (0, elf::R_ARM_ABS32 as u8, 1, 0, near));
+    assert_eq!(relocations[1], (1, elf::R_ARM_ABS32 as u8, 1, 4, far));
+    // only the first one fits into the short form
+    let data = file.section_by_name(".sce.rel").unwrap().data().unwrap();
+    assert_eq!(data[0] & 0xF, 1);
+    assert_eq!(data[8] & 0xF, 0);
+}
+
+#[test]
+fn relocates_the_start_of_a_segment_ending_another_one() {
+    let link = |text_size: usize| {
+        let mut main = new_object();
+        let text = main.section_id(StandardSection::Text);
+        main.append_section_data(text, &vec![0; text_size], 4);
+        add_symbol(&mut main, "_start", text, 0, SymbolKind::Text);
+        let data = main.section_id(StandardSection::Data);
+        main.append_section_data(data, &[0; 4], 4);
+        let value = add_symbol(&mut main, "value", data, 0, SymbolKind::Data);
+        add_abs32(&mut main, data, 0, value);
+        link_objects(
+            "segment-boundary",
+            &[main],
+            OutputOptions::Executable { pic: true },
+        )
+    };
+
+    // fill the text segment up to the page boundary the data segment starts at
+    let out = link(4);
+    let file = object::File::parse(&*out).unwrap();
+    let rest = file.segments().next().unwrap().size() as usize - 4;
+    let out = link(0x1000 - rest);
+    let file = object::File::parse(&*out).unwrap();
+    let text = file.segments().next().unwrap();
+    let data = file.segments().nth(1).unwrap().address();
+    assert_eq!(text.address() + text.size(), data);
+
+    let relocations = sce_relocations(&file);
+    assert_eq!(relocations[0], (1, elf::R_ARM_ABS32 as u8, 1, 0, 0));
+}
+
+#[test]
+fn leaves_fixed_executables_without_relocations() {
+    let mut main = new_object();
+    let text = main.section_id(StandardSection::Text);
+    main.append_section_data(text, &0xe12f_ff1e_u32.to_le_bytes(), 4);
+    let start = add_symbol(&mut main, "_start", text, 0, SymbolKind::Text);
+    let data = main.section_id(StandardSection::Data);
+    main.append_section_data(data, &[0; 4], 4);
+    add_abs32(&mut main, data, 0, start);
+
+    let out = link_objects(
+        "fixed-relocations",
+        &[main],
+        OutputOptions::Executable { pic: false },
+    );
+    assert_eq!(out[16..18], 0xFE00u16.to_le_bytes());
+    let file = object::File::parse(&*out).unwrap();
+    assert!(file.section_by_name(".sce.rel").is_none());
+}
+
+#[test]
+fn redirects_calls_to_function_stubs() {
+    // _start: bl sceKernelExitProcess
+    let mut main = new_object();
+    let text = main.section_id(StandardSection::Text);
+    main.append_section_data(text, &0xEBFF_FFFE_u32.to_le_bytes(), 4);
+    add_symbol(&mut main, "_start", text, 0, SymbolKind::Text);
+    let exit = add_undefined(&mut main, "sceKernelExitProcess");
+    add_reloc(&mut main, text, 0, exit, elf::R_ARM_CALL);
+    let stubs = stub_object(
+        "SceLibKernel",
+        0xCAE9ACE6,
+        &[("sceKernelExitProcess", 0x7595D9AA)],
+        &[],
+    );
+
+    let out = link_objects(
+        "function-stubs",
+        &[main, stubs],
+        OutputOptions::Executable { pic: true },
+    );
+    let file = object::File::parse(&*out).unwrap();
+    assert!(file
+        .section_by_name(".vitalink.fstubs.SceLibKernel")
+        .is_none());
+
+    let stubs = file.section_by_name(".sceStub.text").unwrap();
+    let stub = stubs.address() as u32;
+    assert_eq!(symbol_address(&file, "sceKernelExitProcess") as u32, stub);
+    let placeholder: Vec<_> = (0..4).map(|i| read_word(&file, stub + i * 4)).collect();
+    assert_eq!(placeholder, [0xE3E0_0000, 0xE12F_FF1E, 0xE1A0_0000, 0]);
+
+    let start = symbol_address(&file, "_start") as u32;
+    let bl = read_word(&file, start);
+    let offset = sign_extend((bl & 0x00FF_FFFF) << 2, 26);
+    assert_eq!(start as i64 + 8 + offset, stub as i64);
+
+    let import = file.section_by_name(".sceLib.stub").unwrap();
+    let import = import.data().unwrap();
+    let entry_table = u32::from_le_bytes(import[0x20..0x24].try_into().unwrap());
+    assert_eq!(read_word(&file, entry_table), stub);
+}
+
+#[test]
+fn lays_out_thread_local_storage() {
+    let mut main = new_object();
+    let text = main.section_id(StandardSection::Text);
+    main.append_section_data(text, &[0; 8], 4);
+    add_symbol(&mut main, "_start", text, 0, SymbolKind::Text);
+    let tdata = main.section_id(StandardSection::Tls);
+    main.append_section_data(tdata, &7u32.to_le_bytes(), 4);
+    let seed = add_symbol(&mut main, "seed", tdata, 0, SymbolKind::Tls);
+    let tbss = main.section_id(StandardSection::UninitializedTls);
+    main.append_section_bss(tbss, 8, 8);
+    let counter = add_symbol(&mut main, "counter", tbss, 0, SymbolKind::Tls);
+    add_reloc(&mut main, text, 0, counter, elf::R_ARM_TLS_LE32);
+    add_reloc(&mut main, text, 4, seed, elf::R_ARM_TLS_IE32);
+
+    let out = link_objects("tls", &[main], OutputOptions::Executable { pic: true });
+    let file = object::File::parse(&*out).unwrap();
+
+    // the template is aligned to 8, so it starts 8 bytes past the thread pointer
+    let text = file.section_by_name(".text").unwrap().address() as u32;
+    assert_eq!(read_word(&file, text), 8 + 8);
+    let got = text + 4 + read_word(&file, text + 4);
+    assert_eq!(read_word(&file, got), 8);
+
+    let tdata = file.section_by_name(".tdata").unwrap();
+    let tbss = file.section_by_name(".tbss").unwrap();
+    assert_eq!(tbss.address(), tdata.address() + 8);
+    let info = file.section_by_name(".sceModuleInfo.rodata").unwrap();
+    let info = info.data().unwrap();
+    let word = |offset: usize| u32::from_le_bytes(info[offset..offset + 4].try_into().unwrap());
+    assert_eq!((word(0x38), word(0x3C), word(0x40)), (0, 4, 16));
+    // no sce relocations for thread pointer offsets
+    assert!(sce_relocations(&file)
+        .iter()
+        .all(|&(_, code, ..)| code != elf::R_ARM_TLS_TPOFF32 as u8));
+}
+
+#[test]
+fn fills_tls_get_addr_arguments() {
+    // literals of general and local dynamic code, for `__tls_get_addr`
+    let mut main = new_object();
+    let text = main.section_id(StandardSection::Text);
+    main.append_section_data(text, &[0; 12], 4);
+    add_symbol(&mut main, "_start", text, 0, SymbolKind::Text);
+    let tdata = main.section_id(StandardSection::Tls);
+    main.append_section_data(tdata, &[0; 8], 4);
+    add_symbol(&mut main, "first", tdata, 0, SymbolKind::Tls);
+    let second = add_symbol(&mut main, "second", tdata, 4, SymbolKind::Tls);
+    add_reloc(&mut main, text, 0, second, elf::R_ARM_TLS_GD32);
+    add_reloc(&mut main, text, 4, second, elf::R_ARM_TLS_LDM32);
+    add_reloc(&mut main, text, 8, second, elf::R_ARM_TLS_LDO32);
+
+    let out = link_objects(
+        "tls-get-addr",
+        &[main],
+        OutputOptions::Executable { pic: true },
+    );
+    let file = object::File::parse(&*out).unwrap();
+
+    let text = file.section_by_name(".text").unwrap().address() as u32;
+    let got = text + read_word(&file, text);
+    assert_eq!((read_word(&file, got), read_word(&file, got + 4)), (1, 4));
+    let got = text + 4 + read_word(&file, text + 4);
+    assert_eq!((read_word(&file, got), read_word(&file, got + 4)), (1, 0));
+    assert_eq!(read_word(&file, text + 8), 4);
+}
+
+#[test]
+fn sorts_and_terminates_the_unwind_index() {
+    let mut main = new_object();
+    let mut functions = Vec::new();
+    for name in ["b", "a", "e", "c"] {
+        let section = main.add_section(
+            Vec::new(),
+            format!(".text.{}", name).into_bytes(),
+            SectionKind::Text,
+        );
+        main.append_section_data(section, &0xE12F_FF1E_u32.to_le_bytes(), 4);
+        let symbol = add_symbol(&mut main, name, section, 0, SymbolKind::Text);
+        functions.push((name, symbol));
+        if name == "b" {
+            add_symbol(&mut main, "_start", section, 0, SymbolKind::Text);
+        }
+    }
+    // out of address order, with `e` unwinding like `a` and nothing for `c`
+    for (name, unwind) in [
+        ("a", 0x80A0_A0A0_u32),
+        ("e", 0x80A0_A0A0),
+        ("b", 0x80B0_B0B0),
+    ] {
+        let section = main.add_section(
+            Vec::new(),
+            format!(".ARM.exidx.text.{}", name).into_bytes(),
+            SectionKind::ReadOnlyData,
+        );
+        let mut entry = 0u32.to_le_bytes().to_vec();
+        entry.extend_from_slice(&unwind.to_le_bytes());
+        main.append_section_data(section, &entry, 4);
+        let symbol = functions.iter().find(|(n, _)| *n == name).unwrap().1;
+        add_reloc(&mut main, section, 0, symbol, elf::R_ARM_PREL31);
+    }
+
+    let out = link_objects("exidx", &[main], OutputOptions::Executable { pic: true });
+    let file = object::File::parse(&*out).unwrap();
+
+    let exidx = file.section_by_name(".ARM.exidx").unwrap();
+    let address = exidx.address() as u32;
+    let entries: Vec<_> = (0..exidx.size() as u32 / 8)
+        .map(|i| {
+            let place = address + i * 8;
+            let function = place.wrapping_add(sign_extend(read_word(&file, place), 31) as u32);
+            (function, read_word(&file, place + 4))
+        })
+        .collect();
+    let symbol = |name| symbol_address(&file, name) as u32;
+    assert_eq!(
+        entries,
+        [
+            (symbol("b"), 0x80B0_B0B0),
+            (symbol("a"), 0x80A0_A0A0),
+            (symbol("c"), 1),
+        ]
+    );
+
+    let info = file.section_by_name(".sceModuleInfo.rodata").unwrap();
+    let info = info.data().unwrap();
+    let word = |offset: usize| u32::from_le_bytes(info[offset..offset + 4].try_into().unwrap());
+    let base = file.section_by_name(".text").unwrap().address() as u32;
+    assert_eq!(
+        (word(0x4C), word(0x50)),
+        (address - base, address - base + 24)
+    );
+}
+
+#[test]
+fn terminates_the_unwind_index_before_veneers() {
+    // _start: b far, with unwinding information
+    let mut main = new_object();
+    let text = main.section_id(StandardSection::Text);
+    main.append_section_data(text, &0xEAFF_FFFE_u32.to_le_bytes(), 4);
+    let start = add_symbol(&mut main, "_start", text, 0, SymbolKind::Text);
+    let far = far_symbol(&mut main);
+    add_reloc(&mut main, text, 0, far, elf::R_ARM_JUMP24);
+    let exidx = main.add_section(
+        Vec::new(),
+        b".ARM.exidx.text".to_vec(),
+        SectionKind::ReadOnlyData,
+    );
+    let mut entry = 0u32.to_le_bytes().to_vec();
+    entry.extend_from_slice(&0x80A0_A0A0_u32.to_le_bytes());
+    main.append_section_data(exidx, &entry, 4);
+    add_reloc(&mut main, exidx, 0, start, elf::R_ARM_PREL31);
+
+    let out = link_objects(
+        "exidx-veneers",
+        &[main],
+        OutputOptions::Executable { pic: true },
+    );
+    let file = object::File::parse(&*out).unwrap();
+
+    let exidx = file.section_by_name(".ARM.exidx").unwrap();
+    let address = exidx.address() as u32;
+    let entries: Vec<_> = (0..exidx.size() as u32 / 8)
+        .map(|i| {
+            let place = address + i * 8;
+            let function = place.wrapping_add(sign_extend(read_word(&file, place), 31) as u32);
+            (function, read_word(&file, place + 4))
+        })
+        .collect();
+    // the veneer is not unwound like the code branching to it
+    let symbol = |name| symbol_address(&file, name) as u32;
+    assert_eq!(
+        entries,
+        [(symbol("_start"), 0x80A0_A0A0), (symbol("__far_veneer"), 1)]
+    );
+}
+
+#[test]
+fn builds_eh_frame_hdr_search_table() {
+    let mut main = new_object();
+    let text = main.section_id(StandardSection::Text);
+    main.append_section_data(text, &[0x1E, 0xFF, 0x2F, 0xE1].repeat(2), 4);
+    let a = add_symbol(&mut main, "a", text, 0, SymbolKind::Text);
+    let b = add_symbol(&mut main, "b", text, 4, SymbolKind::Text);
+    add_symbol(&mut main, "_start", text, 0, SymbolKind::Text);
+
+    // a `zR` CIE with pc-relative locations, then FDEs for `b` and `a`
+    let mut frame = Vec::new();
+    frame.extend_from_slice(&16u32.to_le_bytes());
+    frame.extend_from_slice(&0u32.to_le_bytes());
+    frame.extend_from_slice(&[1, b'z', b'R', 0, 2, 0x7C, 14, 1, 0x1B, 0, 0, 0]);
+    for _ in 0..2 {
+        let start = frame.len() as u32;
+        frame.extend_from_slice(&16u32.to_le_bytes());
+        frame.extend_from_slice(&(start + 4).to_le_bytes());
+        frame.extend_from_slice(&[0; 4]);
+        frame.extend_from_slice(&4u32.to_le_bytes());
+        frame.extend_from_slice(&[0; 4]);
+    }
+    let eh_frame = main.add_section(Vec::new(), b".eh_frame".to_vec(), SectionKind::ReadOnlyData);
+    main.append_section_data(eh_frame, &frame, 4);
+    add_reloc(&mut main, eh_frame, 20 + 8, b, elf::R_ARM_REL32);
+    add_reloc(&mut main, eh_frame, 40 + 8, a, elf::R_ARM_REL32);
+    let data = main.section_id(StandardSection::Data);
+    main.append_section_data(data, &[0; 4], 4);
+    let hdr_symbol = add_undefined(&mut main, "__GNU_EH_FRAME_HDR");
+    add_abs32(&mut main, data, 0, hdr_symbol);
+
+    let mut input = link_input(
+        "eh-frame-hdr",
+        &[(String::from("main.o"), main.write().unwrap())],
+        OutputOptions::Executable { pic: true },
+    );
+    input.eh_frame_header = true;
+    link::link(&input).unwrap();
+    let out = read_debug_output(&input);
+    let file = object::File::parse(&*out).unwrap();
+
+    let hdr = file.section_by_name(".eh_frame_hdr").unwrap();
+    let frame = file.section_by_name(".eh_frame").unwrap().address() as u32;
+    let base = hdr.address() as u32;
+    let data = hdr.data().unwrap();
+    assert_eq!(data.len(), 12 + 2 * 8);
+    assert_eq!(data[..4], [1, 0x1B, 0x03, 0x3B]);
+    let word = |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+    assert_eq!(word(4).wrapping_add(base + 4), frame);
+    assert_eq!(word(8), 2);
+    let relative = |address: u32| address.wrapping_sub(base);
+    let table: Vec<_> = (0..2)
+        .map(|i| (word(12 + i * 8), word(16 + i * 8)))
+        .collect();
+    assert_eq!(
+        table,
+        [
+            (
+                relative(symbol_address(&file, "a") as u32),
+                relative(frame + 40)
+            ),
+            (
+                relative(symbol_address(&file, "b") as u32),
+                relative(frame + 20)
+            ),
+        ]
+    );
+
+    let phnum = u16::from_le_bytes(out[44..46].try_into().unwrap()) as usize;
+    let phdr = (0..phnum)
+        .map(|i| &out[52 + i * 32..52 + (i + 1) * 32])
+        .find(|phdr| u32::from_le_bytes(phdr[..4].try_into().unwrap()) == elf::PT_GNU_EH_FRAME)
+        .unwrap();
+    assert_eq!(u32::from_le_bytes(phdr[8..12].try_into().unwrap()), base);
+    assert_eq!(u32::from_le_bytes(phdr[16..20].try_into().unwrap()), 28);
+
+    // the loader does not know the segment, only the debug companion has it
+    let stripped = read_output(&input);
+    let phnum = u16::from_le_bytes(stripped[44..46].try_into().unwrap()) as usize;
+    assert!((0..phnum)
+        .map(|i| &stripped[52 + i * 32..52 + (i + 1) * 32])
+        .all(|phdr| u32::from_le_bytes(phdr[..4].try_into().unwrap()) != elf::PT_GNU_EH_FRAME));
+    // unwinders in the shipped image find the header through its symbol instead
+    let file = object::File::parse(&*stripped).unwrap();
+    let data = file.section_by_name(".data").unwrap().data().unwrap();
+    assert_eq!(data, base.to_le_bytes());
+}
+
+#[test]
+fn drops_frames_of_collected_code() {
+    let mut main = new_object();
+    let start = main.add_section(Vec::new(), b".text._start".to_vec(), SectionKind::Text);
+    main.append_section_data(start, &[0x1E, 0xFF, 0x2F, 0xE1], 4);
+    let start = add_symbol(&mut main, "_start", start, 0, SymbolKind::Text);
+    let dead = main.add_section(Vec::new(), b".text.dead".to_vec(), SectionKind::Text);
+    main.append_section_data(dead, &[0x1E, 0xFF, 0x2F, 0xE1], 4);
+    let dead = add_symbol(&mut main, "dead", dead, 0, SymbolKind::Text);
+
+    // a `zR` CIE, then FDEs for `dead` and `_start`
+    let mut frame = Vec::new();
+    frame.extend_from_slice(&16u32.to_le_bytes());
+    frame.extend_from_slice(&0u32.to_le_bytes());
+    frame.extend_from_slice(&[1, b'z', b'R', 0, 2, 0x7C, 14, 1, 0x1B, 0, 0, 0]);
+    for _ in 0..2 {
+        let start = frame.len() as u32;
+        frame.extend_from_slice(&16u32.to_le_bytes());
+        frame.extend_from_slice(&(start + 4).to_le_bytes());
+        frame.extend_from_slice(&[0; 4]);
+        frame.extend_from_slice(&4u32.to_le_bytes());
+        frame.extend_from_slice(&[0; 4]);
+    }
+    let eh_frame = main.add_section(Vec::new(), b".eh_frame".to_vec(), SectionKind::ReadOnlyData);
+    main.append_section_data(eh_frame, &frame, 4);
+    add_reloc(&mut main, eh_frame, 20 + 8, dead, elf::R_ARM_REL32);
+    add_reloc(&mut main, eh_frame, 40 + 8, start, elf::R_ARM_REL32);
+
+    let mut input = link_input(
+        "eh-frame-gc",
+        &[(String::from("main.o"), main.write().unwrap())],
+        OutputOptions::Executable { pic: true },
+    );
+    input.eh_frame_header = true;
+    input_file(&mut input, 0).gc_sections = true;
+    link::link(&input).unwrap();
+    let out = read_debug_output(&input);
+    let file = object::File::parse(&*out).unwrap();
+
+    assert!(file.symbols().all(|s| s.name() != Ok("dead")));
+    let section = file.section_by_name(".eh_frame").unwrap();
+    let frame = section.address() as u32;
+    let data = section.data().unwrap();
+    let word = |data: &[u8], offset: usize| {
+        u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+    };
+    assert_eq!(data.len(), 40);
+    // the CIE pointer skips the dropped FDE
+    assert_eq!(word(data, 24), 24);
+    assert_eq!(
+        word(data, 28).wrapping_add(frame + 28),
+        symbol_address(&file, "_start") as u32
+    );
+
+    let hdr = file.section_by_name(".eh_frame_hdr").unwrap();
+    let data = hdr.data().unwrap();
+    assert_eq!(data.len(), 12 + 8);
+    assert_eq!(word(data, 8), 1);
+    assert_eq!(
+        word(data, 16).wrapping_add(hdr.address() as u32),
+        frame + 20
+    );
+}
+
+#[test]
+fn applies_z_keywords() {
+    let mut objects = Vec::new();
+    for _ in 0..2 {
+        let mut obj = new_object();
+        let data = obj.section_id(StandardSection::Data);
+        obj.append_section_data(data, &[0; 4], 4);
+        add_symbol(&mut obj, "duplicate", data, 0, SymbolKind::Data);
+        objects.push(obj);
+    }
+    let text = objects[0].section_id(StandardSection::Text);
+    objects[0].append_section_data(text, &[0; 4], 4);
+    add_symbol(&mut objects[0], "_start", text, 0, SymbolKind::Text);
+    let missing = add_undefined(&mut objects[0], "missing");
+    add_abs32(&mut objects[0], text, 0, missing);
+
+    let files: Vec<_> = objects
+        .iter()
+        .enumerate()
+        .map(|(i, obj)| (format!("{}.o", i), obj.write().unwrap()))
+        .collect();
+    let mut input = link_input(
+        "z-keywords",
+        &files,
+        OutputOptions::Executable { pic: false },
+    );
+    input.z_keywords = vec![
+        ZKeyword::MulDefs,
+        ZKeyword::Defs(false),
+        ZKeyword::MaxPageSize(0x4000),
+        ZKeyword::Relro(true),
+        ZKeyword::Unknown(String::from("frobnicate")),
+    ];
+    link::link(&input).unwrap();
+    let out = read_debug_output(&input);
+    let file = object::File::parse(&*out).unwrap();
+
+    let data = file.section_by_name(".data").unwrap();
+    assert_eq!(data.address() % 0x4000, 0);
+    assert_eq!(symbol_address(&file, "duplicate"), data.address());
+}
+
+#[test]
+fn writes_link_map() {
+    let mut main = new_object();
+    let start = main.add_section(Vec::new(), b".text._start".to_vec(), SectionKind::Text);
+    main.append_section_data(start, &[0; 4], 4);
+    add_symbol(&mut main, "_start", start, 0, SymbolKind::Text);
+    let helper = add_undefined(&mut main, "helper");
+    add_abs32(&mut main, start, 0, helper);
+    let dead = main.add_section(Vec::new(), b".text.dead".to_vec(), SectionKind::Text);
+    main.append_section_data(dead, &[0; 4], 4);
+
+    let mut member = new_object();
+    let text = member.section_id(StandardSection::Text);
+    member.append_section_data(text, &[0; 4], 4);
+    add_symbol(&mut member, "helper", text, 0, SymbolKind::Text);
+    let archive = write_archive(&[("helper.o", member.write().unwrap())], false);
+
+    let mut input = link_input(
+        "link-map",
+        &[
+            ("main.o".to_owned(), main.write().unwrap()),
+            ("libhelper.a".to_owned(), archive),
+        ],
+        OutputOptions::Executable { pic: false },
+    );
+    input_file(&mut input, 0).gc_sections = true;
+    let map_file = input.output_file.with_extension("map");
+    input.map_file = Some(map_file.clone());
+    link::link(&input).unwrap();
+    let map = fs::read_to_string(map_file).unwrap();
+
+    let member = map.find("libhelper.a(helper.o)\n").unwrap();
+    assert!(map[member..]
+        .lines()
+        .nth(1)
+        .unwrap()
+        .ends_with("main.o (helper)"));
+    let discarded = map.find("Discarded input sections").unwrap();
+    let memory_map = map.find("Memory map").unwrap();
+    assert!(map[discarded..memory_map].contains(".text.dead"));
+    assert!(map[discarded..memory_map].contains("(unused)"));
+    assert!(map[memory_map..].contains(".sceModuleInfo.rodata"));
+    assert!(map[memory_map..]
+        .lines()
+        .any(|line| line.trim_start().ends_with(" _start")));
+    assert!(map.contains("Exports\n\nNONAME (MAIN_EXPORT)"));
+}
+
+#[test]
+fn rescans_archive_groups() {
+    let mut main = new_object();
+    let text = main.section_id(StandardSection::Text);
+    main.append_section_data(text, &[0; 4], 4);
+    add_symbol(&mut main, "_start", text, 0, SymbolKind::Text);
+    let first = add_undefined(&mut main, "first");
+    add_abs32(&mut main, text, 0, first);
+
+    // `first` needs `second` from the other archive, which needs `third` back from this one
+    let defining = |name: &str, needed: &str| {
+        let mut obj = new_object();
+        let data = obj.section_id(StandardSection::Data);
+        obj.append_section_data(data, &[0; 4], 4);
+        add_symbol(&mut obj, name, data, 0, SymbolKind::Data);
+        if !needed.is_empty() {
+            let needed = add_undefined(&mut obj, needed);
+            add_abs32(&mut obj, data, 0, needed);
+        }
+        obj.write().unwrap()
+    };
+    let files = [
+        ("main.o".to_owned(), main.write().unwrap()),
+        (
+            "libone.a".to_owned(),
+            write_archive(
+                &[
+                    ("first.o", defining("first", "second")),
+                    ("third.o", defining("third", "")),
+                ],
+                false,
+            ),
+        ),
+        (
+            "libtwo.a".to_owned(),
+            write_archive(&[("second.o", defining("second", "third"))], false),
+        ),
+    ];
+
+    let input = link_input(
+        "ungrouped",
+        &files,
+        OutputOptions::Executable { pic: false },
+    );
+    let err = link::link(&input).unwrap_err();
+    assert!(matches!(
+        err,
+        link::LinkError::UndefinedSymbols(references) if references[0].symbol == "third"
+    ));
+
+    let mut input = link_input("group", &files, OutputOptions::Executable { pic: false });
+    let archives = input.inputs.split_off(1);
+    input.inputs.push(InputNode::Group(archives));
+    link::link(&input).unwrap();
+    let out = read_debug_output(&input);
+    let file = object::File::parse(&*out).unwrap();
+    for name in &["first", "second", "third"] {
+        symbol_address(&file, name);
+    }
+}
+
+#[test]
+fn imports_only_needed_stubs() {
+    let mut main = new_object();
+    let text = main.section_id(StandardSection::Text);
+    main.append_section_data(text, &0xe12f_ff1e_u32.to_le_bytes(), 4);
+    add_symbol(&mut main, "_start", text, 0, SymbolKind::Text);
+    let data = main.section_id(StandardSection::Data);
+    main.append_section_data(data, &[0; 4], 4);
+    let exit = add_undefined(&mut main, "sceKernelExitProcess");
+    add_abs32(&mut main, data, 0, exit);
+
+    let stubs = [
+        stub_object(
+            "SceLibKernel",
+            0xCAE9ACE6,
+            &[
+                ("sceKernelExitProcess", 0x7595D9AA),
+                ("sceKernelDelayThread", 0x4B675D05),
+            ],
+            &[],
+        ),
+        stub_object(
+            "SceDisplay",
+            0x4FAACD11,
+            &[("sceDisplaySetFrameBuf", 0x7A410B64)],
+            &[],
+        ),
+        stub_object(
+            "SceCtrl",
+            0xD197E3C7,
+            &[("sceCtrlPeekBufferPositive", 0xA9C3CED6)],
+            &[],
+        ),
+    ];
+    let mut files = vec![("main.o".to_owned(), main.write().unwrap())];
+    for (index, stubs) in stubs.iter().enumerate() {
+        files.push((format!("stubs{}.o", index), stubs.write().unwrap()));
+    }
+    let mut input = link_input(
+        "as-needed",
+        &files,
+        OutputOptions::Executable { pic: false },
+    );
+    // the last library is linked without `--as-needed`
+    input_file(&mut input, 1).as_needed = true;
+    input_file(&mut input, 2).as_needed = true;
+    let map_file = input.output_file.with_extension("map");
+    input.map_file = Some(map_file.clone());
+    link::link(&input).unwrap();
+    let map = fs::read_to_string(map_file).unwrap();
+    let imports = &map[map.find("\nImports\n").unwrap()..];
+
+    assert!(imports.contains("SceLibKernel (0xcae9ace6)"));
+    assert!(imports.contains("sceKernelExitProcess"));
+    assert!(!imports.contains("sceKernelDelayThread"));
+    assert!(!imports.contains("SceDisplay"));
+    assert!(imports.contains("SceCtrl (0xd197e3c7)"));
+    assert!(imports.contains("sceCtrlPeekBufferPositive"));
+
+    let out = read_output(&input);
+    let file = object::File::parse(&*out).unwrap();
+    let imports = file.section_by_name(".sceLib.stub").unwrap();
+    assert_eq!(imports.size(), 2 * 0x34);
+}
+
+/// Link a shared module exporting the `function` and the variable `counter` to
+/// `<dir>/libdemo.vso`.
+fn link_demo_module(dir: &Path, function: &str) {
+    let mut lib = new_object();
+    let text = lib.section_id(StandardSection::Text);
+    lib.append_section_data(text, &[0x70, 0x47, 0x70, 0x47], 4);
+    let function_symbol = add_symbol(&mut lib, function, text, 1, SymbolKind::Text);
+    let data = lib.section_id(StandardSection::Data);
+    lib.append_section_data(data, &[0; 4], 4);
+    let variable = add_symbol(&mut lib, "counter", data, 0, SymbolKind::Data);
+    for symbol in [function_symbol, variable] {
+        lib.symbol_mut(symbol).scope = SymbolScope::Dynamic;
+    }
+
+    let script = ld_version_script::TrivialVersionScript::pretty_parse(&format!(
+        "{{ global: {}; counter; local: *; }};",
+        function
+    ));
+    let mut input = link_input(
+        "vso-module",
+        &[(String::from("lib.o"), lib.write().unwrap())],
+        OutputOptions::Shared {
+            version_script: Some(script),
+        },
+    );
+    input.output_file = dir.join("libdemo.vso");
+    link::link(&input).unwrap();
+}
+
+#[test]
+fn imports_exports_of_dynamic_libraries() {
+    let mut main = new_object();
+    let text = main.section_id(StandardSection::Text);
+    main.append_section_data(text, &[0; 4], 4);
+    add_symbol(&mut main, "_start", text, 0, SymbolKind::Text);
+    let data = main.section_id(StandardSection::Data);
+    main.append_section_data(data, &[0; 8], 4);
+    let function = add_undefined(&mut main, "foo");
+    add_abs32(&mut main, data, 0, function);
+    let variable = add_undefined(&mut main, "counter");
+    add_abs32(&mut main, data, 4, variable);
+
+    let mut input = link_input(
+        "vso-imports",
+        &[("main.o".to_owned(), main.write().unwrap())],
+        OutputOptions::Executable { pic: false },
+    );
+    let dir = input.output_file.parent().unwrap().to_owned();
+    link_demo_module(&dir, "foo");
+    input.library_paths = vec![dir];
+    input.inputs.push(InputNode::Library(input_library(
+        Library::Name("demo".to_owned()),
+        false,
+    )));
+    link::link(&input).unwrap();
+    let out = read_output(&input);
+    let file = object::File::parse(&*out).unwrap();
+
+    let import = file.section_by_name(".sceLib.stub").unwrap();
+    let import = import.data().unwrap();
+    assert_eq!(import.len(), 0x34);
+    let half = |offset: usize| u16::from_le_bytes(import[offset..offset + 2].try_into().unwrap());
+    let field = |offset: usize| u32::from_le_bytes(import[offset..offset + 4].try_into().unwrap());
+    assert_eq!((half(6), half(8)), (1, 1));
+    assert_eq!(
+        field(0x10),
+        psvita_sce_types::nid::Nid::generate(b"libdemo").0
+    );
+    let name = file.section_by_name(".sceImport.rodata").unwrap();
+    let offset = (field(0x14) as u64 - name.address()) as usize;
+    assert!(name.data().unwrap()[offset..].starts_with(b"libdemo\0"));
+    assert_eq!(
+        read_word(&file, field(0x1C)),
+        psvita_sce_types::nid::Nid::generate(b"foo").0
+    );
+    assert_eq!(
+        read_word(&file, field(0x24)),
+        psvita_sce_types::nid::Nid::generate(b"counter").0
+    );
+}
+
+#[test]
+fn calls_rust_functions_of_dynamic_libraries() {
+    // what `examples/dynamic_linking` does with the `foo` of `examples/dylib`
+    let foo = "_ZN20psvita_dylib_example3foo17h0123456789abcdefE";
+    let mut main = new_object();
+    let text = main.section_id(StandardSection::Text);
+    main.append_section_data(text, &0xEBFF_FFFE_u32.to_le_bytes(), 4);
+    add_symbol(&mut main, "_start", text, 0, SymbolKind::Text);
+    let function = add_undefined(&mut main, foo);
+    add_reloc(&mut main, text, 0, function, elf::R_ARM_CALL);
+
+    let mut input = link_input(
+        "rust-dylib",
+        &[("main.o".to_owned(), main.write().unwrap())],
+        OutputOptions::Executable { pic: true },
+    );
+    // rustc passes dynamic libraries by their path
+    let dir = input.output_file.parent().unwrap().to_owned();
+    link_demo_module(&dir, foo);
+    input.inputs.push(InputNode::File(InputFile {
+        path: dir.join("libdemo.vso"),
+        gc_sections: false,
+        whole_archive: false,
+        as_needed: false,
+    }));
+    link::link(&input).unwrap();
+    let out = read_debug_output(&input);
+    let file = object::File::parse(&*out).unwrap();
+
+    let stub = file.section_by_name(".sceStub.text").unwrap().address() as u32;
+    let start = symbol_address(&file, "_start") as u32;
+    let offset = sign_extend((read_word(&file, start) & 0x00FF_FFFF) << 2, 26);
+    assert_eq!(start as i64 + 8 + offset, stub as i64);
+
+    let import = file.section_by_name(".sceLib.stub").unwrap();
+    let import = import.data().unwrap();
+    let field = |offset: usize| u32::from_le_bytes(import[offset..offset + 4].try_into().unwrap());
+    assert_eq!(
+        read_word(&file, field(0x1C)),
+        psvita_sce_types::nid::Nid::generate(foo.as_bytes()).0
+    );
+    assert_eq!(read_word(&file, field(0x20)), stub);
+}
+
+#[test]
+fn skips_unreferenced_dynamic_libraries_as_needed() {
+    let mut main = new_object();
+    let text = main.section_id(StandardSection::Text);
+    main.append_section_data(text, &[0; 4], 4);
+    add_symbol(&mut main, "_start", text, 0, SymbolKind::Text);
+    // declared, but nothing refers to it
+    add_undefined(&mut main, "foo");
+
+    let mut input = link_input(
+        "vso-as-needed",
+        &[("main.o".to_owned(), main.write().unwrap())],
+        OutputOptions::Executable { pic: false },
+    );
+    let dir = input.output_file.parent().unwrap().to_owned();
+    link_demo_module(&dir, "foo");
+    input.library_paths = vec![dir];
+    let mut library = input_library(Library::Name("demo".to_owned()), false);
+    library.as_needed = true;
+    input.inputs.push(InputNode::Library(library));
+    link::link(&input).unwrap();
+    let out = read_output(&input);
+    let file = object::File::parse(&*out).unwrap();
+
+    assert!(file.section_by_name(".sceLib.stub").is_none());
+}
+
+#[test]
+fn imports_unreferenced_dynamic_libraries_without_as_needed() {
+    let mut main = new_object();
+    let text = main.section_id(StandardSection::Text);
+    main.append_section_data(text, &[0; 4], 4);
+    add_symbol(&mut main, "_start", text, 0, SymbolKind::Text);
+
+    let mut input = link_input(
+        "vso-no-as-needed",
+        &[("main.o".to_owned(), main.write().unwrap())],
+        OutputOptions::Executable { pic: false },
+    );
+    let dir = input.output_file.parent().unwrap().to_owned();
+    link_demo_module(&dir, "foo");
+    input.library_paths = vec![dir];
+    input.inputs.push(InputNode::Library(input_library(
+        Library::Name("demo".to_owned()),
+        false,
+    )));
+    link::link(&input).unwrap();
+    let out = read_output(&input);
+    let file = object::File::parse(&*out).unwrap();
+
+    // nothing is imported from the library, but the module still gets loaded
+    let import = file.section_by_name(".sceLib.stub").unwrap();
+    let import = import.data().unwrap();
+    assert_eq!(import.len(), 0x34);
+    let half = |offset: usize| u16::from_le_bytes(import[offset..offset + 2].try_into().unwrap());
+    let field = |offset: usize| u32::from_le_bytes(import[offset..offset + 4].try_into().unwrap());
+    assert_eq!((half(6), half(8)), (0, 0));
+    assert_eq!(
+        field(0x10),
+        psvita_sce_types::nid::Nid::generate(b"libdemo").0
+    );
+}
+
+#[test]
+fn splits_debug_output() {
+    let mut main = new_object();
+    let text = main.section_id(StandardSection::Text);
+    main.append_section_data(text, &0xe12f_ff1e_u32.to_le_bytes(), 4);
+    let start = add_symbol(&mut main, "_start", text, 0, SymbolKind::Text);
+    let debug_info = main.add_section(Vec::new(), b".debug_info".to_vec(), SectionKind::Debug);
+    main.append_section_data(debug_info, &[0; 8], 1);
+    add_abs32(&mut main, debug_info, 4, start);
+
+    let input = link_input(
+        "split-debug",
+        &[("main.o".to_owned(), main.write().unwrap())],
+        OutputOptions::Executable { pic: false },
+    );
+    link::link(&input).unwrap();
+    let out = read_output(&input);
+    let stripped = object::File::parse(&*out).unwrap();
+    let debug_data = read_debug_output(&input);
+    let debug = object::File::parse(&*debug_data).unwrap();
+
+    assert!(stripped.section_by_name(".debug_info").is_none());
+    assert!(stripped.section_by_name(".symtab").is_none());
+    assert_eq!(stripped.symbols().count(), 0);
+
+    // the companion is named with its checksum
+    let link = stripped.section_by_name(".gnu_debuglink").unwrap();
+    let link = link.data().unwrap();
+    assert!(link.starts_with(b"out.debug.elf\0"));
+    // the check value of the zlib CRC-32
+    assert_eq!(link::debug_link_crc(b"123456789"), 0xCBF4_3926);
+    assert_eq!(
+        link[link.len() - 4..],
+        link::debug_link_crc(&debug_data).to_le_bytes()
+    );
+
+    // the debug information refers to the same addresses as the stripped image
+    let start = symbol_address(&debug, "_start") as u32;
+    let info = debug.section_by_name(".debug_info").unwrap();
+    assert_eq!(info.data().unwrap()[4..], start.to_le_bytes());
+    assert_eq!(stripped.entry(), debug.entry());
+    for section in stripped.sections().filter(|s| s.address() != 0) {
+        let copy = debug.section_by_name(section.name().unwrap()).unwrap();
+        assert_eq!(copy.address(), section.address());
+        assert_eq!(copy.data().unwrap(), section.data().unwrap());
+    }
+    let segments = |file: &object::File<'_>| {
+        file.segments()
+            .map(|s| (s.address(), s.size(), s.data().unwrap().to_vec()))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(segments(&stripped), segments(&debug));
+}
@@ -0,0 +1,15 @@
+//! ELF constants specific to SCE executables.
+
+/// `e_type` of executables loaded at a fixed address.
+pub const ET_SCE_EXEC: u16 = 0xFE00;
+/// `e_type` of executables and libraries which the loader relocates.
+pub const ET_SCE_RELEXEC: u16 = 0xFE04;
+
+/// Program header type of the segment holding SCE relocations.
+pub const PT_SCE_RELA: u32 = 0x6000_0000;
+/// Section type of the section holding SCE relocations.
+pub const SHT_SCE_RELA: u32 = 0x6000_0000;
+
+/// Bit position of the segment index in `e_entry`, the rest is the offset of the module
+/// info in that segment.
+pub const ENTRY_SEGMENT_SHIFT: u32 = 30;
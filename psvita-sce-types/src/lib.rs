@@ -2,10 +2,12 @@ use bitflags::bitflags;
 use bytemuck::{Pod, Zeroable};
 use core::marker::PhantomData;
 
+pub mod elf;
 pub mod module_exports;
 pub mod module_imports;
 pub mod module_info;
 pub mod nid;
+pub mod relocation;
 
 bitflags! {
     /// Module type attributes
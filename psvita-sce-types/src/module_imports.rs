@@ -92,11 +92,18 @@ impl FunctionStubPlaceholder {
     };
 }
 
-/// TODO
+/// References to an imported variable, pointed at by `var_entry_table`
+///
+/// `relocations` points at `length` bytes of relocations in the same format as those of the
+/// [`PT_SCE_RELA`](crate::elf::PT_SCE_RELA) segment, see [`crate::relocation`]. Instead of
+/// the segment `r_symseg`, the loader relocates them against the address of the variable,
+/// plus `r_addend`.
 #[repr(C)]
 #[derive(Copy, Clone, Debug, Zeroable, Pod)]
 pub struct SceVariableRelocations {
+    /// Size of the relocations in bytes
     pub length: USize,
+    /// Pointer to the relocations, 0 when there are none
     pub relocations: Ptr<()>,
 }
 
@@ -108,4 +115,18 @@ fn type_assertions() {
     assert_eq!(size_of::<SceModuleImportCommon>(), 0x0C);
     assert_eq!(size_of::<SceModuleImportSized24>(), 0x24);
     assert_eq!(size_of::<SceModuleImportSized34>(), 0x34);
+    assert_eq!(size_of::<SceVariableRelocations>(), 0x8);
+}
+
+#[cfg(test)]
+#[test]
+fn variable_relocation_layout() {
+    use crate::relocation::SceRelocationLong;
+
+    // `R_ARM_ABS32` of the word 0x10 bytes into segment 1, 4 bytes past the variable
+    let relocation = SceRelocationLong::new(0, 2, 1, 0x10, 4).unwrap();
+    assert_eq!(
+        bytemuck::bytes_of(&relocation),
+        [0x00, 0x02, 0x01, 0x00, 0x04, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00]
+    );
 }
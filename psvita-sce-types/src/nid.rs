@@ -25,19 +25,19 @@ impl Nid {
 pub mod noname {
     use super::Nid;
 
-    /// Function 	int module_start(SceSize arglen, const void *argp);
+    /// Function int module_start(SceSize arglen, const void *argp);
     pub const MODULE_START: Nid = Nid(0x935CD196);
-    /// Function 	int module_stop(SceSize arglen, const void *argp);
+    /// Function int module_stop(SceSize arglen, const void *argp);
     pub const MODULE_STOP: Nid = Nid(0x79F8E492);
-    /// Function 	int module_exit(SceSize arglen, const void *argp);
+    /// Function int module_exit(SceSize arglen, const void *argp);
     pub const MODULE_EXIT: Nid = Nid(0x913482A9);
-    /// Function 	int module_bootstart(SceSize arglen, const void *argp);
+    /// Function int module_bootstart(SceSize arglen, const void *argp);
     pub const MODULE_BOOTSTART: Nid = Nid(0x5C424D40);
-    /// Variable 	SceModuleInfo
+    /// Variable SceModuleInfo
     pub const MODULE_INFO: Nid = Nid(0x6C2224BA);
-    /// Variable 	SceProcessParam
+    /// Variable SceProcessParam
     pub const MODULE_PROC_PARAM: Nid = Nid(0x70FBA1E7);
-    /// Variable 	int
+    /// Variable int
     pub const MODULE_SDK_VERSION: Nid = Nid(0x936C8A78);
 
     pub const NID_TABLE: [(&str, Nid); 7] = [
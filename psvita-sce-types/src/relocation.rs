@@ -0,0 +1,89 @@
+//! Relocations in the SCE format, which the loader applies after placing the segments.
+//!
+//! Both forms relocate the word at `offset` bytes into segment `datseg` with the ARM
+//! relocation `code`, against the address `addend` bytes into segment `symseg`. They are
+//! stored in a [`PT_SCE_RELA`](crate::elf::PT_SCE_RELA) segment.
+
+use bytemuck::{Pod, Zeroable};
+
+/// Value of `r_short` for [`SceRelocationLong`].
+pub const FORMAT_LONG: u32 = 0;
+/// Value of `r_short` for [`SceRelocationShort`].
+pub const FORMAT_SHORT: u32 = 1;
+
+/// 8 byte relocation, for offsets and addends which fit into 22 bits.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Zeroable, Pod)]
+pub struct SceRelocationShort {
+    /// `r_short`, `r_symseg`, `r_code`, `r_datseg` and the lowest 12 bits of `r_offset`
+    pub info: u32,
+    /// The upper 10 bits of `r_offset` and `r_addend`
+    pub offset_addend: u32,
+}
+
+impl SceRelocationShort {
+    /// Largest offset the short form can hold.
+    pub const MAX_OFFSET: u32 = (1 << 22) - 1;
+    /// Largest addend the short form can hold.
+    pub const MAX_ADDEND: u32 = (1 << 22) - 1;
+
+    pub fn new(symseg: u8, code: u8, datseg: u8, offset: u32, addend: u32) -> Option<Self> {
+        if offset > Self::MAX_OFFSET || addend > Self::MAX_ADDEND || symseg > 0xF || datseg > 0xF {
+            return None;
+        }
+        Some(SceRelocationShort {
+            info: FORMAT_SHORT
+                | (symseg as u32) << 4
+                | (code as u32) << 8
+                | (datseg as u32) << 16
+                | (offset & 0xFFF) << 20,
+            offset_addend: (offset >> 12) & 0x3FF | addend << 10,
+        })
+    }
+}
+
+/// 12 byte relocation, for any addend.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Zeroable, Pod)]
+pub struct SceRelocationLong {
+    /// `r_short`, `r_symseg`, `r_code`, `r_datseg`, `r_code2` and `r_dist2`
+    pub info: u32,
+    pub addend: u32,
+    pub offset: u32,
+}
+
+impl SceRelocationLong {
+    pub fn new(symseg: u8, code: u8, datseg: u8, offset: u32, addend: u32) -> Option<Self> {
+        if symseg > 0xF || datseg > 0xF {
+            return None;
+        }
+        Some(SceRelocationLong {
+            info: FORMAT_LONG | (symseg as u32) << 4 | (code as u32) << 8 | (datseg as u32) << 16,
+            addend,
+            offset,
+        })
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn type_assertions() {
+    use core::mem::size_of;
+
+    assert_eq!(size_of::<SceRelocationShort>(), 0x8);
+    assert_eq!(size_of::<SceRelocationLong>(), 0xC);
+}
+
+#[cfg(test)]
+#[test]
+fn short_relocation_layout() {
+    let short = SceRelocationShort::new(1, 2, 0, 0x12_3454, 0x12_3456).unwrap();
+    assert_eq!(
+        bytemuck::bytes_of(&short),
+        [0x11, 0x02, 0x40, 0x45, 0x23, 0x59, 0xD1, 0x48]
+    );
+
+    assert!(SceRelocationShort::new(0, 2, 0, 1 << 22, 0).is_none());
+    assert!(SceRelocationShort::new(0, 2, 0, 0, 1 << 22).is_none());
+    assert!(SceRelocationShort::new(0, 2, 0, (1 << 22) - 1, (1 << 22) - 1).is_some());
+}